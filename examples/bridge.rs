@@ -3,21 +3,42 @@ extern crate di;
 use di::Deps;
 use std::sync::Arc;
 
+type ResizeListener = Box<dyn Fn(i32, i32) + Sync + Send>;
+
 struct Window {
-    pub resize_listeners: Vec<Box<Fn(i32, i32) + Sync + Send>>,
+    pub resize_listeners: Vec<(usize, ResizeListener)>,
+    next_listener_id: usize,
 }
 
 struct Logger {
-    pub log_fn: Arc<Fn(&str) + Send + Sync>,
+    pub log_fn: Arc<dyn Fn(&str) + Send + Sync>,
 }
 
+struct ListenerId(usize);
+
 impl Window {
     fn new() -> Window {
-        Window { resize_listeners: Vec::new() }
+        Window {
+            resize_listeners: Vec::new(),
+            next_listener_id: 0,
+        }
+    }
+
+    fn add_resize_listener<F>(&mut self, listener: F) -> ListenerId
+        where F: Fn(i32, i32) + Send + Sync + 'static
+    {
+        let id = self.next_listener_id;
+        self.next_listener_id += 1;
+        self.resize_listeners.push((id, Box::new(listener)));
+        ListenerId(id)
+    }
+
+    fn remove_resize_listener(&mut self, id: &ListenerId) {
+        self.resize_listeners.retain(|&(listener_id, _)| listener_id != id.0);
     }
 
     fn resize(&self, w: i32, h: i32) {
-        for listener in &self.resize_listeners {
+        for (_, listener) in &self.resize_listeners {
             listener(w, h);
         }
     }
@@ -34,17 +55,25 @@ impl Logger {
 fn main() {
     let mut deps = Deps::new();
 
-    deps.bridge(|window: &mut Window, logger: &mut Logger| {
-        // while window and logger both exist, log messages to logger
-        let log_fn_clone = logger.log_fn.clone();
-        window.resize_listeners.push(Box::new(move |w, h| {
-            log_fn_clone(&format!("window resized to w: {} and h: {}", w, h));
-        }));
-        Ok(())
-    });
+    deps.bridge_with_teardown(|_: &Deps, window: &mut Window, logger: &mut Logger| {
+                                  // while window and logger both exist, log messages to logger
+                                  let log_fn_clone = logger.log_fn.clone();
+                                  Ok(window.add_resize_listener(move |w, h| {
+                                      log_fn_clone(&format!("window resized to w: {} and h: {}", w, h));
+                                  }))
+                              },
+                              |window: &mut Window, _: &mut Logger, listener: &mut ListenerId| {
+                                  // when either of them is gone, stop logging
+                                  window.remove_resize_listener(listener);
+                              });
 
     let mut window = deps.create(Window::new()).unwrap();
-    deps.create(Logger::new(|message| println!("message: {:?}", message))).unwrap();
+    let logger = deps.create(Logger::new(|message| println!("message: {:?}", message))).unwrap();
 
     window.lock().unwrap().resize(12, 13);
+
+    drop(logger);
+
+    // nothing is logged
+    window.lock().unwrap().resize(14, 15);
 }
@@ -15,6 +15,12 @@ impl<T> fmt::Debug for Collection<T>
     }
 }
 
+impl<T> Default for Collection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Collection<T> {
     pub fn new() -> Collection<T> {
         Collection { items: Vec::new() }
@@ -53,8 +59,8 @@ impl<T> IntoIterator for Collection<T> {
     }
 }
 
-impl<T> Into<Vec<T>> for Collection<T> {
-    fn into(self) -> Vec<T> {
-        self.items
+impl<T> From<Collection<T>> for Vec<T> {
+    fn from(val: Collection<T>) -> Self {
+        val.items
     }
 }
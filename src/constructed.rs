@@ -1,42 +1,102 @@
 use std::any::Any;
+use std::mem;
 use std::ops::{Deref, DerefMut};
-use std::sync::{Mutex, Arc, LockResult, PoisonError, MutexGuard};
+use std::sync::LockResult;
+use flavor::Flavor;
+use inceptor::{self, Release};
+use locks::{self, Locked, ReadLocked, Share};
+use Result;
 
 enum MaybeMutexGuardValue<'a, T: 'a> {
-    Guard(MutexGuard<'a, T>),
+    Guard(Locked<'a, T>),
     Ref(&'a mut T),
 }
 
 pub struct MaybeMutexGuard<'a, T: 'a> {
     inner: MaybeMutexGuardValue<'a, T>,
+    /// `ChildLock` of bridged scope that owns the value, released after `inner`.
+    _bridged: Option<Box<dyn Any>>,
+    /// Hold of shared value, that defers bridging it until it is unlocked.
+    held: Option<Release>,
+}
+
+enum MaybeReadGuardValue<'a, T: 'a> {
+    Guard(ReadLocked<'a, T>),
+    Ref(&'a T),
+}
+
+/// Read access to scope value, that may be shared with other readers.
+pub struct MaybeReadGuard<'a, T: 'a> {
+    inner: MaybeReadGuardValue<'a, T>,
+    /// Hold of shared value, that defers bridging it until it is unlocked.
+    held: Option<Release>,
 }
 
 #[derive(Debug)]
-pub enum Instance<T> {
+pub enum Instance<T, F: Flavor> {
     Isolated(T),
-    Shared(Arc<Mutex<T>>),
+    Shared(F::Shared<T>),
 }
 
-impl<T> Instance<T> {
+impl<T, F: Flavor> Instance<T, F> {
     pub fn lock<'a>(&'a mut self) -> LockResult<MaybeMutexGuard<'a, T>> {
         match *self {
             Instance::Isolated(ref mut val) => {
-                Ok(MaybeMutexGuard { inner: MaybeMutexGuardValue::Ref(val) })
+                Ok(MaybeMutexGuard { inner: MaybeMutexGuardValue::Ref(val), _bridged: None, held: None })
+            }
+            Instance::Shared(ref val) => {
+                let held = inceptor::hold_until_released(val.address());
+                locks::map_lock(val.lock(), |guard| {
+                    MaybeMutexGuard { inner: MaybeMutexGuardValue::Guard(guard), _bridged: None, held: Some(held) }
+                })
+            }
+        }
+    }
+
+    pub fn read<'a>(&'a self) -> LockResult<MaybeReadGuard<'a, T>> {
+        match *self {
+            Instance::Isolated(ref val) => {
+                Ok(MaybeReadGuard { inner: MaybeReadGuardValue::Ref(val), held: None })
             }
-            Instance::Shared(ref mut val) => {
-                match val.lock() {
-                    Ok(guard) => Ok(MaybeMutexGuard { inner: MaybeMutexGuardValue::Guard(guard) }),
-                    Err(e) => {
-                        Err(PoisonError::new(MaybeMutexGuard {
-                            inner: MaybeMutexGuardValue::Guard(e.into_inner()),
-                        }))
-                    }
-                }
+            Instance::Shared(ref val) => {
+                let held = inceptor::hold_until_released(val.address());
+                locks::map_lock(val.read(), |guard| {
+                    MaybeReadGuard { inner: MaybeReadGuardValue::Guard(guard), held: Some(held) }
+                })
             }
         }
     }
 }
 
+impl<'a, T> MaybeMutexGuard<'a, T> {
+    /// Keep `bridged` `ChildLock` locked for as long as this guard.
+    pub(crate) fn keeping(self, bridged: Option<Box<dyn Any>>) -> Self {
+        MaybeMutexGuard { _bridged: bridged, ..self }
+    }
+
+    /// Unlock the value, and bridge it with values created while it was locked.
+    ///
+    /// Bridges that were deferred because this thread kept the value locked are created
+    /// once it releases all values it locked. All of them are created even if some fail,
+    /// and the first error is returned with the rest as its `Error::others`. Dropping
+    /// the guard creates them too, but ignores their errors.
+    pub fn unlock(self) -> Result<()> {
+        let MaybeMutexGuard { inner, _bridged, held } = self;
+        mem::drop(inner);
+        mem::drop(_bridged);
+        held.map_or(Ok(()), Release::release)
+    }
+}
+
+impl<'a, T> MaybeReadGuard<'a, T> {
+    /// Stop reading the value, see `MaybeMutexGuard::unlock`.
+    pub fn unlock(self) -> Result<()> {
+        let MaybeReadGuard { inner, held } = self;
+        mem::drop(inner);
+        held.map_or(Ok(()), Release::release)
+    }
+}
+
 impl<'a, T> Deref for MaybeMutexGuard<'a, T> {
     type Target = T;
 
@@ -57,14 +117,25 @@ impl<'a, T> DerefMut for MaybeMutexGuard<'a, T> {
     }
 }
 
+impl<'a, T> Deref for MaybeReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.inner {
+            MaybeReadGuardValue::Guard(ref val) => val,
+            MaybeReadGuardValue::Ref(val) => val,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AnyInstance {
-    Isolated(Box<Any>),
-    Shared(Box<Any>),
+    Isolated(Box<dyn Any>),
+    Shared(Box<dyn Any>),
 }
 
 impl AnyInstance {
-    pub fn downcast<T: Any>(self) -> Instance<T> {
+    pub fn downcast<T: Any, F: Flavor>(self) -> Instance<T, F> {
         match self {
             AnyInstance::Isolated(parent) => {
                 Instance::Isolated(*parent.downcast()
@@ -78,10 +149,6 @@ impl AnyInstance {
     }
 }
 
-pub struct Constructed {
-    pub children: Vec<Box<Any>>,
-}
-
-pub struct ConstructedShared {
-    pub children: Vec<Box<Any>>,
+pub struct Constructed<F: Flavor> {
+    pub children: Vec<Box<F::Child>>,
 }
@@ -0,0 +1,164 @@
+//! Tracking of values being created, and detection of cyclic parent-child type chains.
+
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use graph::Node;
+
+thread_local!(
+    /// Types whose dependencies are being created on this thread, outermost first,
+    /// together with serials of the values being created.
+    static CREATING: RefCell<Vec<(Node, u64)>> = const { RefCell::new(Vec::new()) }
+);
+
+/// Serial of the next value entering creation, unique across threads.
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(0);
+
+/// Marks a type as being created on this thread for as long as it lives.
+pub struct CreationGuard;
+
+impl CreationGuard {
+    /// Mark type as being created, or return the repeating chain of types if
+    /// the number of types already being created reached `max_depth`.
+    pub fn enter(node: Node, max_depth: usize) -> Result<CreationGuard, Vec<&'static str>> {
+        CREATING.with(|creating| {
+            let mut creating = creating.borrow_mut();
+            if creating.len() >= max_depth {
+                let nodes: Vec<Node> = creating.iter().map(|&(node, _)| node).collect();
+                return Err(repeating_chain(&nodes, node));
+            }
+            creating.push((node, NEXT_SERIAL.fetch_add(1, Ordering::Relaxed)));
+            Ok(CreationGuard)
+        })
+    }
+}
+
+impl Drop for CreationGuard {
+    fn drop(&mut self) {
+        CREATING.with(|creating| {
+            creating.borrow_mut().pop();
+        });
+    }
+}
+
+/// Serial of the innermost value of specified type that is being created on this thread.
+///
+/// Values created while that value's dependencies are created share it as their ancestor.
+pub fn creating_ancestor(node: Node) -> Option<u64> {
+    CREATING.with(|creating| {
+        creating.borrow()
+            .iter()
+            .rev()
+            .find(|&&(other, _)| other == node)
+            .map(|&(_, serial)| serial)
+    })
+}
+
+/// Returns the chain of types from the last occurrence of `node` in the stack to `node` itself,
+/// or the whole stack if `node` is not in it.
+fn repeating_chain(stack: &[Node], node: Node) -> Vec<&'static str> {
+    let start = stack.iter().rposition(|&other| other == node).unwrap_or(0);
+    stack[start..].iter().chain(Some(&node)).map(|node| node.name()).collect()
+}
+
+/// Find a cycle in graph specified by parent-child edges.
+///
+/// Returns type names in the cycle, starting and ending with the same type.
+pub fn find_cycle(edges: &[(Node, Node)]) -> Option<Vec<&'static str>> {
+    let mut children: HashMap<TypeId, Vec<Node>> = HashMap::new();
+    for &(parent, child) in edges {
+        children.entry(parent.id()).or_default().push(child);
+    }
+
+    let mut visited = Vec::new();
+    for &(parent, _) in edges {
+        let mut path = Vec::new();
+        if let Some(cycle) = visit(&children, parent, &mut path, &mut visited) {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+fn visit(children: &HashMap<TypeId, Vec<Node>>,
+         node: Node,
+         path: &mut Vec<Node>,
+         visited: &mut Vec<TypeId>)
+         -> Option<Vec<&'static str>> {
+    if path.contains(&node) {
+        return Some(repeating_chain(path, node));
+    }
+    if visited.contains(&node.id()) {
+        return None;
+    }
+    visited.push(node.id());
+    path.push(node);
+    for &child in children.get(&node.id()).map(|c| &c[..]).unwrap_or(&[]) {
+        if let Some(cycle) = visit(children, child, path, visited) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use graph::Node;
+    use super::*;
+
+    #[test]
+    fn should_not_find_cycle_in_tree() {
+        let edges = vec![(Node::of::<u8>(), Node::of::<u16>()),
+                         (Node::of::<u8>(), Node::of::<u32>()),
+                         (Node::of::<u16>(), Node::of::<u32>())];
+
+        assert_eq!(None, find_cycle(&edges));
+    }
+
+    #[test]
+    fn should_find_cycle() {
+        let edges = vec![(Node::of::<u8>(), Node::of::<u16>()),
+                         (Node::of::<u16>(), Node::of::<u32>()),
+                         (Node::of::<u32>(), Node::of::<u16>())];
+
+        assert_eq!(Some(vec!["u16", "u32", "u16"]), find_cycle(&edges));
+    }
+
+    #[test]
+    fn should_find_self_cycle() {
+        let edges = vec![(Node::of::<u8>(), Node::of::<u8>())];
+
+        assert_eq!(Some(vec!["u8", "u8"]), find_cycle(&edges));
+    }
+
+    #[test]
+    fn should_stop_entering_at_max_depth() {
+        let _a = CreationGuard::enter(Node::of::<u8>(), 3).unwrap();
+        let _b = CreationGuard::enter(Node::of::<u16>(), 3).unwrap();
+        let _c = CreationGuard::enter(Node::of::<u8>(), 3).unwrap();
+
+        assert_eq!(vec!["u16", "u8", "u16"],
+                   CreationGuard::enter(Node::of::<u16>(), 3).err().unwrap());
+    }
+
+    #[test]
+    fn should_find_innermost_ancestor() {
+        assert_eq!(None, creating_ancestor(Node::of::<u8>()));
+
+        let _a = CreationGuard::enter(Node::of::<u8>(), 8).unwrap();
+        let outer = creating_ancestor(Node::of::<u8>()).unwrap();
+        {
+            let _b = CreationGuard::enter(Node::of::<u16>(), 8).unwrap();
+            let _c = CreationGuard::enter(Node::of::<u8>(), 8).unwrap();
+            let inner = creating_ancestor(Node::of::<u8>()).unwrap();
+
+            assert!(inner != outer);
+            assert!(creating_ancestor(Node::of::<u16>()).is_some());
+        }
+
+        assert_eq!(Some(outer), creating_ancestor(Node::of::<u8>()));
+        assert_eq!(None, creating_ancestor(Node::of::<u16>()));
+    }
+}
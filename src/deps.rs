@@ -1,511 +1,2697 @@
-use std::any::{Any, TypeId};
+use std::any::{self, Any, TypeId};
+use std::hash::Hash;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use constructed::{Constructed, ConstructedShared, AnyInstance};
-use inceptor::{Inceptor, Destructor};
-use {Result, Collection, Scope};
+use std::result;
+use constructed::{Constructed, AnyInstance};
+use flavor::{Erase, Flavor, Threaded};
+use inceptor::{self, Inceptor, Group};
+use locks::{self, Locked, Share, SharedLock};
+use cycles::{self, CreationGuard};
+use {Result, Collection, CreateError, Error, ErrorKind, Scope};
+use scope::{self, AnyDropHook, DropHooks};
+use inspect::Child;
+use graph::{Graph, Edge, EdgeKind, Node};
+use order::{Order, Ordered};
+
+/// Registers shared parent at its position in bridge inceptor, and creates dependencies
+/// for the bridged children it produces.
+type Incept<F> = fn(&Deps<F>, &mut AnyInstance, &Arc<Mutex<Inceptor<F>>>, usize) -> Result<Constructed<F>>;
+
+/// Constructor of children for a type, run in resolved order with other constructors for it.
+enum Constructor<F: Flavor> {
+    /// Constructor registered with `attach` or `collectable`, which locks the value
+    /// if a bridge already shared it.
+    Isolated(Arc<F::Constructor>),
+    /// Constructor that shares the value with a bridge inceptor, at specified position.
+    Shared(Incept<F>, Arc<Mutex<Inceptor<F>>>, usize),
+}
+
+impl<F: Flavor> Clone for Constructor<F> {
+    fn clone(&self) -> Self {
+        match *self {
+            Constructor::Isolated(ref constructor) => Constructor::Isolated(constructor.clone()),
+            Constructor::Shared(incept, ref inceptor, index) => Constructor::Shared(incept, inceptor.clone(), index),
+        }
+    }
+}
+
+/// Default limit of nested dependency creation, see `Deps::set_max_depth`.
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Created parent, together with all of its children.
+type Created<F> = (AnyInstance, Vec<Box<<F as Flavor>::Child>>);
+
+/// Dependencies of values, registered as callbacks that create them.
+///
+/// `Deps` and everything they create can be used from any thread, so values and callbacks
+/// registered in them are `Send + Sync`. `LocalDeps` have the same registrations without
+/// that requirement.
+pub struct Deps<F: Flavor = Threaded> {
+    /// Registrations, shared with bridges deferred until their parents are unlocked.
+    registry: Arc<Registry<F>>,
+}
 
-pub struct Deps {
+struct Registry<F: Flavor> {
     /// Ignored type ().
     empty_type: TypeId,
-    /// List of functions that constructs all childs for a type
-    /// and returns value wrapped in Any that must live as long as the parent type.
-    isolated_constructors: HashMap<TypeId,
-                                       Vec<Box<Fn(&Deps, &mut Box<Any>) -> Result<Constructed> + Send + Sync>>>,
-    /// List of functions that constructs all childs for a type wrapped in Box<Arc<Mutex<T>>> as Box<Any>
+    /// List of functions that constructs all childs for a type, attached or bridged,
     /// and returns value wrapped in Any that must live as long as the parent type.
-    shared_constructors: HashMap<TypeId, Vec<Box<Fn(&Deps, &mut Box<Any>) -> Result<ConstructedShared> + Send + Sync>>>,
+    constructors: HashMap<TypeId, Ordered<Constructor<F>>>,
     /// List of callbacks to invoke after a value and all its dependencies were created.
-    type_scope_created: HashMap<TypeId,
-                                Vec<Box<Fn(&Deps, &mut AnyInstance) -> Result<()> + Send + Sync>>>,
-    /// List of inceptors that manage shared dependency bridge creation for type pairs.
-    inceptors: HashMap<(TypeId, TypeId), Box<Any>>,
+    type_scope_created: HashMap<TypeId, Ordered<Arc<F::Action>>>,
+    /// List of functions that replace a value before any of its dependencies are created.
+    decorators: HashMap<TypeId, Vec<Arc<F::Decorator>>>,
+    /// List of callbacks to invoke before any dependencies of a value are created.
+    type_scope_creating: HashMap<TypeId, Vec<Arc<F::CreatingHook>>>,
+    /// List of checks a value must pass before any of its dependencies are created.
+    validators: HashMap<TypeId, Vec<Arc<F::Validator>>>,
+    /// List of callbacks to invoke before children of a scope are destroyed.
+    type_scope_dropping: HashMap<TypeId, Vec<AnyDropHook<F>>>,
+    /// List of callbacks to invoke after children of a scope are destroyed.
+    type_scope_dropped: HashMap<TypeId, Vec<AnyDropHook<F>>>,
+    /// Description of all registrations.
+    graph: Graph,
+    /// Maximum number of nested values being created on a thread.
+    max_depth: usize,
+    /// Lock used to share values of types not found in `shared_locks`.
+    shared_lock: SharedLock,
+    /// Lock used to share values of specific types.
+    shared_locks: HashMap<TypeId, SharedLock>,
+}
+
+impl<F: Flavor> Clone for Registry<F> {
+    fn clone(&self) -> Self {
+        Registry {
+            empty_type: self.empty_type,
+            constructors: self.constructors.clone(),
+            type_scope_created: self.type_scope_created.clone(),
+            decorators: self.decorators.clone(),
+            type_scope_creating: self.type_scope_creating.clone(),
+            validators: self.validators.clone(),
+            type_scope_dropping: self.type_scope_dropping.clone(),
+            type_scope_dropped: self.type_scope_dropped.clone(),
+            graph: self.graph.clone(),
+            max_depth: self.max_depth,
+            shared_lock: self.shared_lock,
+            shared_locks: self.shared_locks.clone(),
+        }
+    }
 }
 
-fn to_shared<T: Any>(not_shared: Box<Any>) -> Box<Any> {
+fn to_shared<T: Any, F: Flavor>(not_shared: Box<dyn Any>, lock: SharedLock) -> Box<dyn Any> {
     let parent: T = *not_shared.downcast::<T>()
         .expect("expected downcast to P when \
                  changing to shared P");
-    Box::new(Arc::new(Mutex::new(parent)))
+    Box::new(F::share(parent, lock))
+}
+
+fn from_shared<T: Any, F: Flavor>(shared: Box<dyn Any>) -> Box<dyn Any> {
+    let parent = *shared.downcast::<F::Shared<T>>()
+        .expect("expected downcast to shared P when \
+                 changing to not shared P");
+    let parent: T = parent.try_unwrap()
+        .ok()
+        .expect("expected arc to be last remaining");
+    Box::new(parent)
+}
+
+/// Type information and conversions for a type-erased value.
+#[derive(Clone, Copy)]
+struct AnyType {
+    node: Node,
+    /// Conversion to value shared in specified lock, or in `Rc<RefCell<T>>` for `LocalDeps`.
+    to_shared: fn(Box<dyn Any>, SharedLock) -> Box<dyn Any>,
+    from_shared: fn(Box<dyn Any>) -> Box<dyn Any>,
+}
+
+impl AnyType {
+    fn of<T: Any, F: Flavor>() -> AnyType {
+        AnyType {
+            node: Node::of::<T>(),
+            to_shared: to_shared::<T, F>,
+            from_shared: from_shared::<T, F>,
+        }
+    }
+}
+
+/// Drop children in reverse order of construction and give back the parent value, after
+/// discarding combinations deferred since `deferred` mark, which may keep it shared.
+fn rollback<C>(deferred: usize,
+               parent: AnyInstance,
+               children: Vec<C>,
+               from_shared: fn(Box<dyn Any>) -> Box<dyn Any>)
+               -> Box<dyn Any> {
+    inceptor::discard_deferred(deferred);
+    scope::drop_in_reverse(children);
+    match parent {
+        AnyInstance::Isolated(parent) => parent,
+        AnyInstance::Shared(parent) => from_shared(parent),
+    }
+}
+
+fn order_error(reason: String, parent: Node) -> Error {
+    Error::new(reason).returned_from(ErrorKind::Order, None).within(parent.name())
+}
+
+fn cycle_error(chain: &[&'static str]) -> Error {
+    Error::new(format!("cyclic dependency {}", chain.join(" -> "))).returned_from(ErrorKind::Cycle, None)
+}
+
+/// Bridged parent that is passed to bridge constructor as `F::Shared<P>` wrapped in Any.
+fn bridged<F: Flavor, P: Any>(parent: &dyn Any) -> &F::Shared<P> {
+    parent.downcast_ref::<F::Shared<P>>().expect("expected downcast to bridged parent")
+}
+
+fn lock_bridged<P: Any, S: Share<P>>(parent: &S) -> Locked<'_, P> {
+    parent.lock().expect("expected to lock bridged parent")
+}
+
+/// Lock two bridged parents in global lock order.
+fn lock2<'a, F: Flavor, P1: Any, P2: Any>(parents: &[&'a dyn Any]) -> (Locked<'a, P1>, Locked<'a, P2>) {
+    let (s1, s2) = (bridged::<F, P1>(parents[0]), bridged::<F, P2>(parents[1]));
+    let mut order = [(locks::rank::<P1, _>(s1), 0), (locks::rank::<P2, _>(s2), 1)];
+    order.sort();
+    let (mut p1, mut p2) = (None, None);
+    for &(_, position) in &order {
+        match position {
+            0 => p1 = Some(lock_bridged(s1)),
+            _ => p2 = Some(lock_bridged(s2)),
+        }
+    }
+    (p1.unwrap(), p2.unwrap())
+}
+
+/// Lock three bridged parents in global lock order.
+fn lock3<'a, F: Flavor, P1: Any, P2: Any, P3: Any>(parents: &[&'a dyn Any])
+                                                   -> (Locked<'a, P1>, Locked<'a, P2>, Locked<'a, P3>) {
+    let (s1, s2) = (bridged::<F, P1>(parents[0]), bridged::<F, P2>(parents[1]));
+    let s3 = bridged::<F, P3>(parents[2]);
+    let mut order = [(locks::rank::<P1, _>(s1), 0), (locks::rank::<P2, _>(s2), 1), (locks::rank::<P3, _>(s3), 2)];
+    order.sort();
+    let (mut p1, mut p2, mut p3) = (None, None, None);
+    for &(_, position) in &order {
+        match position {
+            0 => p1 = Some(lock_bridged(s1)),
+            1 => p2 = Some(lock_bridged(s2)),
+            _ => p3 = Some(lock_bridged(s3)),
+        }
+    }
+    (p1.unwrap(), p2.unwrap(), p3.unwrap())
+}
+
+/// Lock four bridged parents in global lock order.
+#[allow(clippy::type_complexity)]
+fn lock4<'a, F: Flavor, P1: Any, P2: Any, P3: Any, P4: Any>
+    (parents: &[&'a dyn Any])
+     -> (Locked<'a, P1>, Locked<'a, P2>, Locked<'a, P3>, Locked<'a, P4>) {
+    let (s1, s2) = (bridged::<F, P1>(parents[0]), bridged::<F, P2>(parents[1]));
+    let (s3, s4) = (bridged::<F, P3>(parents[2]), bridged::<F, P4>(parents[3]));
+    let mut order = [(locks::rank::<P1, _>(s1), 0),
+                     (locks::rank::<P2, _>(s2), 1),
+                     (locks::rank::<P3, _>(s3), 2),
+                     (locks::rank::<P4, _>(s4), 3)];
+    order.sort();
+    let (mut p1, mut p2, mut p3, mut p4) = (None, None, None, None);
+    for &(_, position) in &order {
+        match position {
+            0 => p1 = Some(lock_bridged(s1)),
+            1 => p2 = Some(lock_bridged(s2)),
+            2 => p3 = Some(lock_bridged(s3)),
+            _ => p4 = Some(lock_bridged(s4)),
+        }
+    }
+    (p1.unwrap(), p2.unwrap(), p3.unwrap(), p4.unwrap())
+}
+
+impl Default for Deps {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Deps {
     pub fn new() -> Deps {
-        Deps {
-            empty_type: TypeId::of::<()>(),
-            isolated_constructors: HashMap::new(),
-            shared_constructors: HashMap::new(),
-            type_scope_created: HashMap::new(),
-            inceptors: HashMap::new(),
-        }
+        Deps::with_registry()
     }
 
-    /// Create dependencies for specified `obj` and return a wrapper `Scope` object.
+    /// Set the lock used to share values with bridges, for types that have no lock
+    /// set with `set_shared_lock_of`.
     ///
-    /// The wrapper `Scope` keeps ownership of all children together with parent object.
-    pub fn create<P: Any>(&self, obj: P) -> Result<Scope<P>> {
-        let (parent, deps) =
-            try!(self.create_deps_for_any_parent(TypeId::of::<P>(), Box::new(obj), to_shared::<P>));
-        Ok(Scope::from_any_instance(parent, deps))
+    /// Values are shared in `SharedLock::Mutex` by default.
+    pub fn set_shared_lock(&mut self, lock: SharedLock) {
+        self.registry_mut().shared_lock = lock;
     }
 
-    /// Collect all the items registered as `collectable` into a `Collection` of that type.
-    pub fn collect<C: Any>(&self) -> Result<Collection<C>> {
-        self.create(Collection::new()).map(|v| v.explode())
+    /// Set the lock used to share values of type `T` with bridges.
+    ///
+    /// Read-mostly values shared in `SharedLock::RwLock` can be read with `Scope::read`
+    /// from many threads at once.
+    pub fn set_shared_lock_of<T: Any>(&mut self, lock: SharedLock) {
+        self.registry_mut().shared_locks.insert(TypeId::of::<T>(), lock);
     }
 
     pub fn when_ready<T, F>(&mut self, action: F)
         where T: 'static + Any,
               F: for<'r> Fn(&Deps, &mut T) -> Result<()> + 'static + Send + Sync
     {
-        match self.type_scope_created.entry(TypeId::of::<T>()) {
-            Entry::Occupied(mut list) => {
-                list.get_mut().push(into_action_with_deps(action));
-            }
-            Entry::Vacant(e) => {
-                e.insert(vec![into_action_with_deps(action)]);
-            }
-        };
+        self.when_ready_ordered(Order::new(), action).expect("expected unordered registration to succeed")
+    }
+
+    /// Same as `when_ready`, but the action runs in specified `order` among other actions
+    /// for `T`.
+    ///
+    /// Returns `ErrorKind::Order` error, and does not register the action, if `order`
+    /// contradicts the order of actions already registered.
+    pub fn when_ready_ordered<T, F>(&mut self, order: Order, action: F) -> Result<()>
+        where T: 'static + Any,
+              F: for<'r> Fn(&Deps, &mut T) -> Result<()> + 'static + Send + Sync
+    {
+        self.register_when_ready(order, action)
+    }
+
+    /// Replace every value of type `T` passed to `create` with the one returned by
+    /// `decorator`, before any other callbacks registered for `T` see it.
+    ///
+    /// Decorators are applied in order of registration, each to the value returned
+    /// by the previous one. A decorator fails by returning `CreateError` with a value of `T`,
+    /// which `try_create` gives back with `ErrorKind::Decorate` error.
+    pub fn decorate<T, F>(&mut self, decorator: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&Deps, T) -> result::Result<T, CreateError<T>> + 'static + Send + Sync
+    {
+        self.register_decorate(decorator)
+    }
+
+    /// Invoke `hook` with every value of type `T` passed to `create`, after decorators
+    /// and before any of its dependencies are created.
+    pub fn before_create<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&Deps, &mut T) + 'static + Send + Sync
+    {
+        self.register_before_create(hook)
+    }
+
+    /// Check every value of type `T` passed to `create`, after decorators and `before_create`
+    /// hooks, and before any of its dependencies are created.
+    ///
+    /// If the check fails, the creation fails with `ErrorKind::Validate` error, without
+    /// invoking any constructors or bridging the value.
+    pub fn validate<T, F>(&mut self, check: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&Deps, &T) -> Result<()> + 'static + Send + Sync
+    {
+        self.register_validate(check)
+    }
+
+    /// Invoke `hook` with the value of every `Scope<T>` that is dropped or exploded,
+    /// before its children are destroyed.
+    ///
+    /// Shared value is locked while `hook` runs.
+    pub fn when_dropping<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&mut T) + 'static + Send + Sync
+    {
+        self.register_when_dropping(hook)
+    }
+
+    /// Invoke `hook` with the value of every `Scope<T>` that is dropped or exploded,
+    /// after its children are destroyed.
+    ///
+    /// Shared value is locked while `hook` runs.
+    pub fn when_dropped<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&mut T) + 'static + Send + Sync
+    {
+        self.register_when_dropped(hook)
     }
 
     /// Single dependency on a parent.
     pub fn attach<P, C, F>(&mut self, constructor: F)
         where P: 'static + Any, // Parent
-              C: 'static + Any, // Child
+              C: 'static + Any + Send + Sync, // Child
               F: for<'r> Fn(&Deps, &mut P) -> Result<C> + 'static + Send + Sync
     {
-        if TypeId::of::<C>() == self.empty_type {
-            self.register_isolated_constructor::<P>(into_isolated_constructor_with_ignored_child_deps(constructor));
-        } else {
-            self.register_isolated_constructor::<P>(into_isolated_constructor_with_child_deps(constructor));
-        }
+        self.attach_ordered(Order::new(), constructor).expect("expected unordered registration to succeed")
+    }
+
+    /// Same as `attach`, but the constructor runs in specified `order` among other
+    /// constructors attached to `P`.
+    ///
+    /// Returns `ErrorKind::Order` error, and does not register the constructor, if `order`
+    /// contradicts the order of constructors already attached.
+    pub fn attach_ordered<P, C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where P: 'static + Any, // Parent
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_attach(order, constructor)
     }
 
     /// Single dependency on multiple parents.
+    ///
+    /// The child is created for every live pair of `P1` and `P2`, and is destroyed
+    /// as soon as any of its parents is destroyed.
+    ///
+    /// Every call registers an independent bridge, even if there already is a bridge
+    /// for the same parent types.
+    ///
+    /// Like in `attach`, the constructor receives `Deps` that are creating the parent, so it
+    /// can create or collect other values.
+    ///
+    /// Panics if `P1` and `P2` are the same type, which `bridge_peers` is for. The same holds
+    /// for the parents of every other bridge variant.
     pub fn bridge<P1, P2, C, F>(&mut self, constructor: F)
         where P1: 'static + Any + Send + Sync, // Parent 1
               P2: 'static + Any + Send + Sync, // Parent 2
-              C: 'static + Any, // Child
-              F: for<'r> Fn(&mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
-    {
-        // Get or insert inceptor that is used to manage P1 and P2 instances.
-        let inceptor_1 = match self.inceptors
-            .entry((TypeId::of::<P1>(), TypeId::of::<P2>())) {
-            Entry::Occupied(entry) => {
-                entry.get()
-                    .downcast_ref::<Arc<Mutex<Inceptor<P1, P2>>>>()
-                    .expect("expected to find Inceptor of correct type in map")
-                    .clone()
-            }
-            Entry::Vacant(entry) => {
-                let arc = Arc::new(Mutex::new(if TypeId::of::<C>() == self.empty_type {
-                    Inceptor::new_with_ignored_return_val(constructor)
-                } else {
-                    Inceptor::new_with_return_val(constructor)
-                }));
-                entry.insert(Box::new(arc.clone()));
-                arc
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
+    {
+        self.bridge_ordered(Order::new(), constructor).expect("expected unordered registration to succeed")
+    }
+
+    /// Same as `bridge`, but each parent is shared with the bridge in specified `order`
+    /// among other constructors attached or bridged to its type.
+    ///
+    /// The parent is wrapped in lock before the first bridge it takes part in, so constructors
+    /// that run after that lock it, the same as `when_ready` actions. Other bridge variants
+    /// take part in the order without constraints.
+    ///
+    /// Returns `ErrorKind::Order` error, and does not register the bridge, if `order`
+    /// contradicts the order of constructors already registered for either parent.
+    pub fn bridge_ordered<P1, P2, C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge(order, constructor)
+    }
+
+    /// Single dependency on multiple parents that were created under the same `S`.
+    ///
+    /// The child is created only for pairs of `P1` and `P2` that share the innermost
+    /// `S` ancestor, which is the `S` whose dependencies (or their dependencies) they were
+    /// created as, on the same thread. Either parent may also be that `S` itself.
+    /// Parents created outside of any `S` are only bridged with each other.
+    pub fn bridge_within<S, P1, P2, C, F>(&mut self, constructor: F)
+        where S: 'static + Any, // Scope
+              P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge_within::<S, P1, P2, C, F>(constructor)
+    }
+
+    /// Single dependency on multiple parents whose keys match.
+    ///
+    /// The child is created only for pairs of `P1` and `P2` for which `key1` and `key2`
+    /// return equal keys. Keys are extracted once, when a parent is created, and matching
+    /// parents are looked up by key hash.
+    pub fn bridge_by_key<P1, P2, K, C, K1, K2, F>(&mut self, key1: K1, key2: K2, constructor: F)
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              K: 'static + Hash + Eq + Send + Sync, // Key
+              C: 'static + Any + Send + Sync, // Child
+              K1: for<'r> Fn(&P1) -> K + 'static + Send + Sync,
+              K2: for<'r> Fn(&P2) -> K + 'static + Send + Sync,
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge_by_key(key1, key2, constructor)
+    }
+
+    /// Single dependency on multiple parents that satisfy a predicate.
+    ///
+    /// The child is created only for pairs of `P1` and `P2` accepted by `filter`, which is
+    /// checked once for every pair, when the later of the two is created.
+    pub fn bridge_filtered<P1, P2, C, P, F>(&mut self, filter: P, constructor: F)
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              C: 'static + Any + Send + Sync, // Child
+              P: for<'r> Fn(&P1, &P2) -> bool + 'static + Send + Sync,
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge_filtered(filter, constructor)
+    }
+
+    /// Single dependency on two distinct instances of the same type.
+    ///
+    /// The child is created once for every unordered pair of live `T` instances, and is
+    /// destroyed as soon as any of them is destroyed. The earlier created instance of the pair
+    /// is passed first. An instance is never paired with itself.
+    pub fn bridge_peers<T, C, F>(&mut self, constructor: F)
+        where T: 'static + Any + Send + Sync, // Peer
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut T, &mut T) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge_peers(constructor)
+    }
+
+    /// Single dependency on multiple parents, with a `teardown` that reverts the changes
+    /// `constructor` made to parents.
+    ///
+    /// The `teardown` is invoked with both parents and the child when any of the parents is
+    /// destroyed, before the child itself is dropped.
+    pub fn bridge_with_teardown<P1, P2, C, F, T>(&mut self, constructor: F, teardown: T)
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2) -> Result<C> + 'static + Send + Sync,
+              T: for<'r> Fn(&mut P1, &mut P2, &mut C) + 'static + Send + Sync
+    {
+        self.register_bridge_with_teardown(constructor, teardown)
+    }
+
+    /// Single dependency on three parents.
+    ///
+    /// The child is created for every live combination of `P1`, `P2` and `P3`, and is destroyed
+    /// as soon as any of its parents is destroyed.
+    pub fn bridge3<P1, P2, P3, C, F>(&mut self, constructor: F)
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              P3: 'static + Any + Send + Sync, // Parent 3
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2, &mut P3) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge3(constructor)
+    }
+
+    /// Single dependency on four parents.
+    ///
+    /// The child is created for every live combination of `P1`, `P2`, `P3` and `P4`, and is
+    /// destroyed as soon as any of its parents is destroyed.
+    pub fn bridge4<P1, P2, P3, P4, C, F>(&mut self, constructor: F)
+        where P1: 'static + Any + Send + Sync, // Parent 1
+              P2: 'static + Any + Send + Sync, // Parent 2
+              P3: 'static + Any + Send + Sync, // Parent 3
+              P4: 'static + Any + Send + Sync, // Parent 4
+              C: 'static + Any + Send + Sync, // Child
+              F: for<'r> Fn(&Deps, &mut P1, &mut P2, &mut P3, &mut P4) -> Result<C> + 'static + Send + Sync
+    {
+        self.register_bridge4(constructor)
+    }
+
+    pub fn collectable<C, F>(&mut self, constructor: F)
+        where C: 'static + Any,
+              F: for<'r> Fn(&Deps) -> C + 'static + Send + Sync
+    {
+        self.collectable_ordered(Order::new(), constructor)
+            .expect("expected unordered registration to succeed")
+    }
+
+    /// Same as `collectable`, but the item is collected in specified `order` among other
+    /// items of type `C`.
+    ///
+    /// Returns `ErrorKind::Order` error, and does not register the constructor, if `order`
+    /// contradicts the order of constructors already registered.
+    pub fn collectable_ordered<C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where C: 'static + Any,
+              F: for<'r> Fn(&Deps) -> C + 'static + Send + Sync
+    {
+        self.register_collectable(order, constructor)
+    }
+}
+
+impl<F: Flavor> Deps<F> {
+    pub(crate) fn with_registry() -> Deps<F> {
+        Deps {
+            registry: Arc::new(Registry {
+                empty_type: TypeId::of::<()>(),
+                constructors: HashMap::new(),
+                type_scope_created: HashMap::new(),
+                decorators: HashMap::new(),
+                type_scope_creating: HashMap::new(),
+                validators: HashMap::new(),
+                type_scope_dropping: HashMap::new(),
+                type_scope_dropped: HashMap::new(),
+                graph: Graph::new(),
+                max_depth: DEFAULT_MAX_DEPTH,
+                shared_lock: SharedLock::default(),
+                shared_locks: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Deps with the same registrations, that bridge deferred combinations after their
+    /// parents are unlocked.
+    pub(crate) fn handle(&self) -> Deps<F> {
+        Deps { registry: self.registry.clone() }
+    }
+
+    /// Registrations to change, copied if bridges deferred by a value locked with
+    /// `Scope::lock` or `Scope::read` still share them, so those bridges are created with
+    /// the registrations they were deferred with.
+    fn registry_mut(&mut self) -> &mut Registry<F> {
+        Arc::make_mut(&mut self.registry)
+    }
+
+    /// Set the maximum number of values that can be created inside each other.
+    ///
+    /// Exceeding it fails the creation with `ErrorKind::Cycle` error instead of
+    /// overflowing the stack, which would happen if attached types form a cycle.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.registry_mut().max_depth = max_depth;
+    }
+
+    /// Description of all registrations, in order of registration.
+    ///
+    /// It can be written in Graphviz DOT or JSON format for review.
+    pub fn graph(&self) -> &Graph {
+        &self.registry.graph
+    }
+
+    /// Check that no chain of types registered with `attach` leads back to
+    /// its starting type.
+    ///
+    /// Returns `ErrorKind::Cycle` error with type names of the cycle as its path.
+    pub fn check_cycles(&self) -> Result<()> {
+        let attached: Vec<(Node, Node)> = self.registry.graph
+            .edges()
+            .iter()
+            .filter(|edge| edge.kind() == EdgeKind::Attach)
+            .filter_map(|edge| edge.child().map(|child| (edge.parents()[0], child)))
+            .collect();
+        match cycles::find_cycle(&attached) {
+            Some(cycle) => {
+                let mut error = cycle_error(&cycle);
+                for name in cycle.into_iter().rev() {
+                    error = error.within(name);
+                }
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Create dependencies for specified `obj` and return a wrapper `Scope` object.
+    ///
+    /// The wrapper `Scope` keeps ownership of all children together with parent object.
+    ///
+    /// If any of the constructors fails, children created so far are destroyed in reverse
+    /// order of their construction.
+    pub fn create<P: Any>(&self, obj: P) -> Result<Scope<P, F>> {
+        self.try_create(obj).map_err(CreateError::into_error)
+    }
+
+    /// Create dependencies for specified `obj` and return a wrapper `Scope` object,
+    /// or give back the `obj` together with the error if that fails.
+    ///
+    /// If any of the constructors fails, children created so far are destroyed in reverse
+    /// order of their construction, before the `obj` is given back. If a decorator fails,
+    /// the value it gave back is given back instead.
+    pub fn try_create<P: Any>(&self, obj: P) -> result::Result<Scope<P, F>, CreateError<P>> {
+        match self.create_deps_for_any_parent(AnyType::of::<P, F>(), Box::new(obj)) {
+            Ok((parent, deps)) => Ok(Scope::with_hooks(parent, deps, self.drop_hooks::<P>())),
+            Err(e) => {
+                let (parent, error) = e.into_parts();
+                Err(CreateError::new(*parent.downcast::<P>().expect("expected to downcast given back P"),
+                                     error))
+            }
+        }
+    }
+
+    /// Collect all the items registered as `collectable` into a `Collection` of that type.
+    pub fn collect<C: Any>(&self) -> Result<Collection<C>> {
+        self.create(Collection::new()).map(|v| v.explode())
+    }
+
+    fn create_deps_for_any_parent(&self,
+                                  any_type: AnyType,
+                                  mut parent_not_shared: Box<dyn Any>)
+                                  -> result::Result<Created<F>, CreateError<Box<dyn Any>>> {
+        let _creating = match CreationGuard::enter(any_type.node, self.registry.max_depth) {
+            Ok(guard) => guard,
+            Err(chain) => return Err(CreateError::new(parent_not_shared, cycle_error(&chain))),
+        };
+
+        // Combinations deferred from here on are dropped if creation fails, because they
+        // may keep the value or its children alive.
+        let deferred = inceptor::deferred_mark();
+        let mut deps = Vec::new();
+
+        // First, replace the value with decorated one
+
+        if let Some(decorators) = self.registry.decorators.get(&any_type.node.id()) {
+            for decorator in decorators {
+                parent_not_shared = match decorator(self, parent_not_shared) {
+                    Ok(decorated) => decorated,
+                    Err(e) => {
+                        inceptor::discard_deferred(deferred);
+                        let (parent, any_err) = e.into_parts();
+                        return Err(CreateError::new(parent, any_err.within(any_type.node.name())));
+                    }
+                };
+            }
+        }
+
+        // Then, let hooks see the value before anything is created for it
+
+        if let Some(hooks) = self.registry.type_scope_creating.get(&any_type.node.id()) {
+            for hook in hooks {
+                hook(self, &mut parent_not_shared);
+            }
+        }
+
+        // Next, reject the value if it fails any check
+
+        if let Some(validators) = self.registry.validators.get(&any_type.node.id()) {
+            for validator in validators {
+                if let Err(any_err) = validator(self, &*parent_not_shared) {
+                    inceptor::discard_deferred(deferred);
+                    return Err(CreateError::new(parent_not_shared, any_err.within(any_type.node.name())));
+                }
+            }
+        }
+
+        // After that, invoke constructors in their resolved order. The value is wrapped in lock
+        // before the first bridge it takes part in, and stays in AnyInstance::Isolated
+        // if there are none.
+
+        let mut parent_result = AnyInstance::Isolated(parent_not_shared);
+        if let Some(constructors) = self.registry.constructors.get(&any_type.node.id()) {
+            for constructor in constructors.iter() {
+                let constructed = match *constructor {
+                    Constructor::Isolated(ref construct) => construct(self, &mut parent_result),
+                    Constructor::Shared(incept, ref inceptor, index) => {
+                        parent_result = self.share(any_type, parent_result);
+                        incept(self, &mut parent_result, inceptor, index)
+                    }
+                };
+                match constructed {
+                    Ok(Constructed { children }) => deps.extend(children),
+                    Err(any_err) => {
+                        let parent = rollback(deferred, parent_result, deps, any_type.from_shared);
+                        return Err(CreateError::new(parent, any_err.within(any_type.node.name())));
+                    }
+                };
+            }
+        }
+
+        // Execute post create actions for the value
+
+        if let Some(actions) = self.registry.type_scope_created.get(&any_type.node.id()) {
+            for action in actions.iter() {
+                if let Err(any_err) = action(self, &mut parent_result) {
+                    let parent = rollback(deferred, parent_result, deps, any_type.from_shared);
+                    return Err(CreateError::new(parent, any_err.within(any_type.node.name())));
+                }
+            }
+        }
+
+        Ok((parent_result, deps))
+    }
+
+    /// Wrap value in the lock set for its type, or in `Rc<RefCell<T>>` for `LocalDeps`,
+    /// unless it already is.
+    fn share(&self, any_type: AnyType, parent: AnyInstance) -> AnyInstance {
+        match parent {
+            AnyInstance::Isolated(parent) => {
+                let lock = self.registry.shared_locks.get(&any_type.node.id()).cloned().unwrap_or(self.registry.shared_lock);
+                AnyInstance::Shared((any_type.to_shared)(parent, lock))
             }
+            shared => shared,
+        }
+    }
+
+    /// Hooks registered to run when scope of type `T` is destroyed.
+    fn drop_hooks<T: Any>(&self) -> DropHooks<T, F> {
+        let id = TypeId::of::<T>();
+        DropHooks::new(self.registry.type_scope_dropping.get(&id).map_or(&[], |hooks| hooks),
+                       self.registry.type_scope_dropped.get(&id).map_or(&[], |hooks| hooks))
+    }
+
+    /// Registration with child `C` unless it is `()`.
+    fn edge<C: Any>(&self, kind: EdgeKind, parents: Vec<Node>) -> Edge {
+        let child = if TypeId::of::<C>() == self.registry.empty_type {
+            None
+        } else {
+            Some(Node::of::<C>())
         };
+        Edge::new(kind, parents, child)
+    }
+
+    /// Add registration to graph, with child `C` unless it is `()`.
+    fn register_edge<C: Any>(&mut self, kind: EdgeKind, parents: Vec<Node>) -> Edge {
+        let edge = self.edge::<C>(kind, parents);
+        self.registry_mut().graph.push(edge.clone());
+        edge
+    }
+
+    /// Create inceptor for bridge of `C` with specified parents, which creates dependencies
+    /// for values returned by `constructor` unless they are `()`.
+    fn new_inceptor<C>(&self, parents: &[BridgeParent<F>], constructor: Arc<F::BridgeConstructor>) -> Inceptor<F>
+        where C: 'static + Any,
+              F: Erase<Scope<C, F>>
+    {
+        let edge = self.edge::<C>(EdgeKind::Bridge, BridgeParent::nodes(parents));
+        let inceptor = Inceptor::new(edge, constructor);
+        if TypeId::of::<C>() == self.registry.empty_type {
+            inceptor
+        } else {
+            inceptor.with_child(into_bridged_scope::<F, C>)
+        }
+    }
+
+    /// Share configured inceptor with constructors of its parents, registered in specified
+    /// `order` at their positions, or once for peers that are all taken from a single list.
+    ///
+    /// Returns `ErrorKind::Order` error, and registers nothing, if `order` contradicts the
+    /// order of constructors of any parent.
+    ///
+    /// Panics if the same type is bridged at more than one position without being peers,
+    /// because a value of that type would have to be locked twice to be paired with itself.
+    fn register_inceptor(&mut self, parents: &[BridgeParent<F>], inceptor: Inceptor<F>, order: Order) -> Result<()> {
+        let positions = if inceptor.is_peers() { &parents[..1] } else { parents };
+        for (index, parent) in positions.iter().enumerate() {
+            assert!(positions[..index].iter().all(|other| other.node != parent.node),
+                    "bridge has parent {} at more than one position, use bridge_peers to bridge \
+                     values of the same type",
+                    parent.node.name());
+        }
+        let edge = inceptor.edge().clone();
+        let inceptor = Arc::new(Mutex::new(inceptor));
+        // Orders of all parents are checked before any of them is changed.
+        let mut ordered = Vec::with_capacity(positions.len());
+        for (index, parent) in positions.iter().enumerate() {
+            let mut constructors = self.registry.constructors.get(&parent.node.id()).cloned().unwrap_or_default();
+            constructors.push(order.clone(), Constructor::Shared(parent.incept, inceptor.clone(), index))
+                .map_err(|reason| order_error(reason, parent.node))?;
+            ordered.push((parent.node.id(), constructors));
+        }
+        self.registry_mut().constructors.extend(ordered);
+        self.registry_mut().graph.push(edge);
+        Ok(())
+    }
+
+    /// Register child constructor that will be invoked in specified `order` when the parent
+    /// `P` type is created.
+    fn register_constructor<P: Any>(&mut self, order: Order, constructor: Constructor<F>) -> Result<()> {
+        self.registry_mut().constructors
+            .entry(TypeId::of::<P>())
+            .or_default()
+            .push(order, constructor)
+            .map_err(|reason| order_error(reason, Node::of::<P>()))
+    }
+}
+
+/// Registrations shared by `Deps` and `LocalDeps`, without thread-safety bounds on values.
+///
+/// Callbacks and child scopes are erased into `F` with `Erase`, which requires them to be
+/// `Send + Sync` for `Deps`.
+impl<F: Flavor> Deps<F> {
+    pub(crate) fn register_when_ready<T, A>(&mut self, order: Order, action: A) -> Result<()>
+        where T: 'static + Any,
+              A: for<'r> Fn(&Deps<F>, &mut T) -> Result<()> + 'static,
+              F: Erase<A>
+    {
+        let action = F::action(action, |action, deps, parent| {
+            with_parent::<F, T, _, _>(parent, |parent| action(deps, parent))
+                .map_err(|e| e.returned_from(ErrorKind::WhenReady, None))
+        });
+        self.registry_mut().type_scope_created
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(order, action)
+            .map_err(|reason| order_error(reason, Node::of::<T>()))?;
+        self.register_edge::<()>(EdgeKind::WhenReady, vec![Node::of::<T>()]);
+        Ok(())
+    }
+
+    pub(crate) fn register_before_create<T, H>(&mut self, hook: H)
+        where T: 'static + Any,
+              H: for<'r> Fn(&Deps<F>, &mut T) + 'static,
+              F: Erase<H>
+    {
+        self.register_edge::<()>(EdgeKind::BeforeCreate, vec![Node::of::<T>()]);
+        let hook = F::creating_hook(hook, |hook, deps, value| {
+            hook(deps, value.downcast_mut::<T>().expect("expected to downcast type in before create hook"))
+        });
+        match self.registry_mut().type_scope_creating.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut list) => {
+                list.get_mut().push(hook);
+            }
+            Entry::Vacant(e) => {
+                e.insert(vec![hook]);
+            }
+        };
+    }
+
+    pub(crate) fn register_decorate<T, D>(&mut self, decorator: D)
+        where T: 'static + Any,
+              D: for<'r> Fn(&Deps<F>, T) -> result::Result<T, CreateError<T>> + 'static,
+              F: Erase<D>
+    {
+        self.register_edge::<()>(EdgeKind::Decorate, vec![Node::of::<T>()]);
+        let decorator = F::decorator(decorator, |decorator, deps, value| {
+            let value = *value.downcast::<T>().expect("expected to downcast type in decorator");
+            match decorator(deps, value) {
+                Ok(decorated) => Ok(Box::new(decorated) as Box<dyn Any>),
+                Err(e) => {
+                    let (value, error) = e.into_parts();
+                    Err(CreateError::new(Box::new(value) as Box<dyn Any>,
+                                         error.returned_from(ErrorKind::Decorate, None)))
+                }
+            }
+        });
+        match self.registry_mut().decorators.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut list) => {
+                list.get_mut().push(decorator);
+            }
+            Entry::Vacant(e) => {
+                e.insert(vec![decorator]);
+            }
+        };
+    }
+
+    pub(crate) fn register_validate<T, V>(&mut self, check: V)
+        where T: 'static + Any,
+              V: for<'r> Fn(&Deps<F>, &T) -> Result<()> + 'static,
+              F: Erase<V>
+    {
+        self.register_edge::<()>(EdgeKind::Validate, vec![Node::of::<T>()]);
+        let validator = F::validator(check, |check, deps, value| {
+            check(deps, value.downcast_ref::<T>().expect("expected to downcast type in validator"))
+                .map_err(|e| e.returned_from(ErrorKind::Validate, None))
+        });
+        match self.registry_mut().validators.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut list) => {
+                list.get_mut().push(validator);
+            }
+            Entry::Vacant(e) => {
+                e.insert(vec![validator]);
+            }
+        };
+    }
+
+    pub(crate) fn register_when_dropping<T, H>(&mut self, hook: H)
+        where T: 'static + Any,
+              H: for<'r> Fn(&mut T) + 'static,
+              F: Erase<H>
+    {
+        self.register_edge::<()>(EdgeKind::WhenDropping, vec![Node::of::<T>()]);
+        match self.registry_mut().type_scope_dropping.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut list) => {
+                list.get_mut().push(into_drop_hook::<F, T, _>(hook));
+            }
+            Entry::Vacant(e) => {
+                e.insert(vec![into_drop_hook::<F, T, _>(hook)]);
+            }
+        };
+    }
+
+    pub(crate) fn register_when_dropped<T, H>(&mut self, hook: H)
+        where T: 'static + Any,
+              H: for<'r> Fn(&mut T) + 'static,
+              F: Erase<H>
+    {
+        self.register_edge::<()>(EdgeKind::WhenDropped, vec![Node::of::<T>()]);
+        match self.registry_mut().type_scope_dropped.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut list) => {
+                list.get_mut().push(into_drop_hook::<F, T, _>(hook));
+            }
+            Entry::Vacant(e) => {
+                e.insert(vec![into_drop_hook::<F, T, _>(hook)]);
+            }
+        };
+    }
+
+    pub(crate) fn register_attach<P, C, A>(&mut self, order: Order, constructor: A) -> Result<()>
+        where P: 'static + Any, // Parent
+              C: 'static + Any, // Child
+              A: for<'r> Fn(&Deps<F>, &mut P) -> Result<C> + 'static,
+              F: Erase<A> + Erase<Scope<C, F>>
+    {
+        let any_constructor = if TypeId::of::<C>() == self.registry.empty_type {
+            into_isolated_constructor_with_ignored_child_deps(constructor)
+        } else {
+            into_isolated_constructor_with_child_deps(constructor)
+        };
+        self.register_constructor::<P>(order, Constructor::Isolated(any_constructor))?;
+        self.register_edge::<C>(EdgeKind::Attach, vec![Node::of::<P>()]);
+        Ok(())
+    }
+
+    pub(crate) fn register_bridge<P1, P2, C, B>(&mut self, order: Order, constructor: B) -> Result<()>
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<Scope<C, F>>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+            let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+            bridge_value(constructor(deps, &mut p1, &mut p2))
+        }));
+        self.register_inceptor(&parents, inceptor, order)
+    }
+
+    pub(crate) fn register_bridge_within<S, P1, P2, C, B>(&mut self, constructor: B)
+        where S: 'static + Any, // Scope
+              P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<Scope<C, F>> + Erase<Node> + Erase<u64>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+                let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+                bridge_value(constructor(deps, &mut p1, &mut p2))
+            }))
+            .with_group(F::bridge_group(Node::of::<S>(), |&scope, _, _| cycles::creating_ancestor(scope).map(Group::new)));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge_by_key<P1, P2, K, C, K1, K2, B>(&mut self, key1: K1, key2: K2, constructor: B)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              K: 'static + Hash + Eq, // Key
+              C: 'static + Any, // Child
+              K1: for<'r> Fn(&P1) -> K + 'static,
+              K2: for<'r> Fn(&P2) -> K + 'static,
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<Scope<C, F>> + Erase<(K1, K2)> + Erase<K>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+                let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+                bridge_value(constructor(deps, &mut p1, &mut p2))
+            }))
+            .with_group(F::bridge_group((key1, key2), |(key1, key2), index, parent| {
+                Some(Group::new(match index {
+                    0 => key1(&lock_bridged(bridged::<F, P1>(parent))),
+                    _ => key2(&lock_bridged(bridged::<F, P2>(parent))),
+                }))
+            }));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge_filtered<P1, P2, C, P, B>(&mut self, filter: P, constructor: B)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              P: for<'r> Fn(&P1, &P2) -> bool + 'static,
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<Scope<C, F>> + Erase<P>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+                let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+                bridge_value(constructor(deps, &mut p1, &mut p2))
+            }))
+            .with_filter(F::bridge_filter(filter, |filter, parents| {
+                let (p1, p2) = lock2::<F, P1, P2>(parents);
+                filter(&p1, &p2)
+            }));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge_peers<T, C, B>(&mut self, constructor: B)
+        where T: 'static + Any, // Peer
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut T, &mut T) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<T>> + Erase<Scope<C, F>>
+    {
+        let parents = [BridgeParent::of::<T>(), BridgeParent::of::<T>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+                let (mut p1, mut p2) = lock2::<F, T, T>(parents);
+                bridge_value(constructor(deps, &mut p1, &mut p2))
+            }))
+            .with_peers();
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge_with_teardown<P1, P2, C, B, T>(&mut self, constructor: B, teardown: T)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2) -> Result<C> + 'static,
+              T: for<'r> Fn(&mut P1, &mut P2, &mut C) + 'static,
+              F: Erase<B> + Erase<T> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<Scope<C, F>>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>()];
+        let edge = self.edge::<C>(EdgeKind::Bridge, BridgeParent::nodes(&parents));
+        // Child is kept even if it is `()`, so that there is something to tear down.
+        let constructor = F::bridge_constructor(constructor, |constructor, deps, parents| {
+            let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+            constructor(deps, &mut p1, &mut p2)
+                .map(|child| Some(Box::new(child) as Box<dyn Any>))
+                .map_err(|e| e.returned_from(ErrorKind::Bridge, Some(any::type_name::<C>())))
+        });
+        let inceptor = Inceptor::new(edge, constructor)
+            .with_child(into_bridged_scope::<F, C>)
+            .with_teardown(F::bridge_teardown(teardown, |teardown, parents, child| {
+                let (mut p1, mut p2) = lock2::<F, P1, P2>(parents);
+                let mut child = child.as_any_mut()
+                    .downcast_mut::<Scope<C, F>>()
+                    .expect("expected downcast to bridged child")
+                    .lock()
+                    .expect("expected to lock bridged child");
+                teardown(&mut p1, &mut p2, &mut child)
+            }));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge3<P1, P2, P3, C, B>(&mut self, constructor: B)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              P3: 'static + Any, // Parent 3
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2, &mut P3) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<<F as Flavor>::Shared<P3>> + Erase<Scope<C, F>>
+    {
+        let parents = [BridgeParent::of::<P1>(), BridgeParent::of::<P2>(), BridgeParent::of::<P3>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+            let (mut p1, mut p2, mut p3) = lock3::<F, P1, P2, P3>(parents);
+            bridge_value(constructor(deps, &mut p1, &mut p2, &mut p3))
+        }));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_bridge4<P1, P2, P3, P4, C, B>(&mut self, constructor: B)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              P3: 'static + Any, // Parent 3
+              P4: 'static + Any, // Parent 4
+              C: 'static + Any, // Child
+              B: for<'r> Fn(&Deps<F>, &mut P1, &mut P2, &mut P3, &mut P4) -> Result<C> + 'static,
+              F: Erase<B> + Erase<<F as Flavor>::Shared<P1>> + Erase<<F as Flavor>::Shared<P2>> + Erase<<F as Flavor>::Shared<P3>> + Erase<<F as Flavor>::Shared<P4>>,
+              F: Erase<Scope<C, F>>
+    {
+        let parents = [BridgeParent::of::<P1>(),
+                       BridgeParent::of::<P2>(),
+                       BridgeParent::of::<P3>(),
+                       BridgeParent::of::<P4>()];
+        let inceptor = self.new_inceptor::<C>(&parents, F::bridge_constructor(constructor, |constructor, deps, parents| {
+            let (mut p1, mut p2, mut p3, mut p4) = lock4::<F, P1, P2, P3, P4>(parents);
+            bridge_value(constructor(deps, &mut p1, &mut p2, &mut p3, &mut p4))
+        }));
+        self.register_inceptor(&parents, inceptor, Order::new()).expect("expected unordered registration to succeed");
+    }
+
+    pub(crate) fn register_collectable<C, A>(&mut self, order: Order, constructor: A) -> Result<()>
+        where C: 'static + Any,
+              A: for<'r> Fn(&Deps<F>) -> C + 'static,
+              F: Erase<A>
+    {
+        let constructor = F::constructor(constructor, |constructor, deps, parent| {
+            with_parent::<F, Collection<C>, _, _>(parent, |parent| {
+                parent.push(constructor(deps));
+                Ok(())
+            })?;
+            Ok(Constructed { children: vec![] })
+        });
+        self.register_constructor::<Collection<C>>(order, Constructor::Isolated(constructor))?;
+        self.register_edge::<C>(EdgeKind::Collectable, vec![Node::of::<Collection<C>>()]);
+        Ok(())
+    }
+}
+
+/// Invoke `f` with the value of type `P`, locking it if it is shared.
+///
+/// Bridging values created by `f` with shared value is deferred until it is unlocked.
+fn with_parent<F, P, R, W>(parent: &mut AnyInstance, f: W) -> Result<R>
+    where F: Flavor,
+          P: 'static + Any,
+          W: FnOnce(&mut P) -> Result<R>
+{
+    match *parent {
+        AnyInstance::Isolated(ref mut value) => {
+            f(value.downcast_mut::<P>().expect("expected to downcast type of AnyInstance::Isolated"))
+        }
+        AnyInstance::Shared(ref mut value) => {
+            let value = value.downcast_mut::<F::Shared<P>>()
+                .expect("expected to downcast type of AnyInstance::Shared");
+            let result = {
+                let _hold = inceptor::hold(&[value.address()]);
+                f(&mut value.lock().expect("expected to lock value of AnyInstance::Shared"))
+            };
+            let returned = result?;
+            inceptor::run_deferred()?;
+            Ok(returned)
+        }
+    }
+}
+
+fn into_drop_hook<F, T, H>(hook: H) -> AnyDropHook<F>
+    where H: for<'r> Fn(&mut T) + 'static,
+          T: 'static + Any,
+          F: Erase<H>
+{
+    F::drop_hook(hook, |hook, value| {
+        hook(value.downcast_mut::<T>().expect("expected to downcast type in drop hook"))
+    })
+}
+
+/// Bridge parent type, with registration of its constructor that shares its values
+/// with the bridge inceptor.
+struct BridgeParent<F: Flavor> {
+    node: Node,
+    incept: Incept<F>,
+}
+
+impl<F: Flavor> BridgeParent<F> {
+    fn of<P: 'static + Any>() -> BridgeParent<F>
+        where F: Erase<<F as Flavor>::Shared<P>>
+    {
+        BridgeParent {
+            node: Node::of::<P>(),
+            incept: incept::<F, P>,
+        }
+    }
+
+    fn nodes(parents: &[BridgeParent<F>]) -> Vec<Node> {
+        parents.iter().map(|parent| parent.node).collect()
+    }
+}
+
+/// Value returned by bridge constructor of `C`, or `None` if it is `()`, with errors reported
+/// as returned from that bridge.
+fn bridge_value<C: 'static + Any>(value: Result<C>) -> Result<Option<Box<dyn Any>>> {
+    let value = value.map_err(|e| e.returned_from(ErrorKind::Bridge, Some(any::type_name::<C>())))?;
+    if TypeId::of::<C>() == TypeId::of::<()>() {
+        Ok(None)
+    } else {
+        Ok(Some(Box::new(value)))
+    }
+}
+
+/// Registers the shared parent `P` at inceptor position `index` and creates dependencies
+/// for the bridged children it produces.
+///
+/// Bridged children are owned by the inceptor, the parent scope only keeps a `Destructor`
+/// that releases the parent (and all children bridged with it) when dropped.
+fn incept<F, P>(deps: &Deps<F>,
+                parent: &mut AnyInstance,
+                bridge: &Arc<Mutex<Inceptor<F>>>,
+                index: usize)
+                -> Result<Constructed<F>>
+    where F: Erase<<F as Flavor>::Shared<P>>,
+          P: 'static + Any // Parent
+{
+    let parent = match *parent {
+        AnyInstance::Shared(ref parent) => parent.downcast_ref::<F::Shared<P>>().expect("expected downcast P").clone(),
+        AnyInstance::Isolated(_) => panic!("expected bridged parent to be shared"),
+    };
+    let address = parent.address();
+    let destructor = inceptor::incept(bridge, deps, index, Arc::from(F::erase(parent)), address)?;
+    Ok(Constructed { children: vec![F::destructor(destructor)] })
+}
+
+/// Create dependencies for value returned by bridge constructor.
+fn into_bridged_scope<F, C>(deps: &Deps<F>, value: Box<dyn Any>) -> Result<Box<F::Child>>
+    where F: Erase<Scope<C, F>>,
+          C: 'static + Any
+{
+    let (child, child_deps) = deps.create_deps_for_any_parent(AnyType::of::<C, F>(), value)
+        .map_err(CreateError::into_error)?;
+    Ok(F::child(Scope::<C, F>::with_hooks(child, child_deps, deps.drop_hooks::<C>())))
+}
+
+fn into_isolated_constructor_with_child_deps<F, P, C, A>(constructor: A) -> Arc<F::Constructor>
+    where A: for<'r> Fn(&Deps<F>, &mut P) -> Result<C> + 'static,
+          P: 'static + Any,
+          C: 'static + Any,
+          F: Erase<A> + Erase<Scope<C, F>>
+{
+    F::constructor(constructor, |constructor, deps, parent| {
+        let child = with_parent::<F, P, _, _>(parent, |parent| {
+            constructor(deps, parent).map_err(|e| e.returned_from(ErrorKind::Attach, Some(any::type_name::<C>())))
+        })?;
+        let child = deps.create(child)?;
+        Ok(Constructed { children: vec![F::child(child)] })
+    })
+}
+
+fn into_isolated_constructor_with_ignored_child_deps<F, P, C, A>(constructor: A) -> Arc<F::Constructor>
+    where A: for<'r> Fn(&Deps<F>, &mut P) -> Result<C> + 'static,
+          P: 'static + Any,
+          C: 'static + Any,
+          F: Erase<A>
+{
+    F::constructor(constructor, |constructor, deps, parent| {
+        with_parent::<F, P, _, _>(parent, |parent| {
+            constructor(deps, parent).map_err(|e| e.returned_from(ErrorKind::Attach, None))
+        })?;
+        Ok(Constructed { children: vec![] })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {Deps, Collection, CreateError, Edge, EdgeKind, Error, ErrorKind, ExplodeReason, LockInversion, Node, Scope};
+    use {Order, SharedLock};
+    use constructed::Instance;
+    use locks::{Share, Shared};
+    use {BridgeInfo, ScopeInfo, Sharing, Visitor};
+    use std::any;
+    use std::marker::PhantomData;
+    use std::mem;
+    use std::panic;
+    use std::thread;
+    use std::time::Duration;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    struct A(String);
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    struct B(String);
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    struct C(String);
+
+    #[test]
+    fn creates_dependency() {
+        let mut deps = Deps::new();
+
+        // here we want to know what is the state of dependency in closure, hence
+        // shared mutable reference to it
+        let created_b_ref = Arc::new(Mutex::new(None));
+
+        deps.attach({
+            let created_b_ref = created_b_ref.clone();
+            move |_: &Deps, a: &mut A| {
+                let b = B([&a.0[..], "+B"].concat());
+                *created_b_ref.lock().unwrap() = Some(b.clone());
+                Ok(b)
+            }
+        });
+
+        deps.create(A("Hello".into())).unwrap();
+
+        assert_eq!("Hello+B",
+                   (*created_b_ref.lock().unwrap()).clone().unwrap().0);
+    }
+
+    #[test]
+    fn creates_dependency_of_dependency() {
+        let mut deps = Deps::new();
+
+        // here we want to know what is the state of dependency in closure, hence
+        // shared mutable reference to it
+        let created_c_ref = Arc::new(Mutex::new(None));
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B([&a.0[..], "+B"].concat())));
+
+        deps.attach({
+            let created_c_ref = created_c_ref.clone();
+            move |_: &Deps, b: &mut B| {
+                let c = C([&b.0[..], "+C"].concat());
+                *created_c_ref.lock().unwrap() = Some(c.clone());
+                Ok(c)
+            }
+        });
+
+        deps.create(A("Hello".into())).unwrap();
+
+        assert_eq!("Hello+B+C",
+                   (*created_c_ref.lock().unwrap()).clone().unwrap().0);
+    }
+
+    #[test]
+    fn creates_mutable_dependency() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| {
+            *a = A("Hi!".into());
+            Ok(())
+        });
+
+        let mut a = deps.create(A("Hello".into())).unwrap();
+        let al = a.lock().unwrap();
+
+        assert_eq!("Hi!", al.0);
+    }
+
+    #[test]
+    fn should_work_accross_threads() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, _: &mut A| Ok(B("b".into())));
+        deps.attach(|_: &Deps, _: &mut B| Ok(C("c".into())));
+
+        let dep_refs = Arc::new(deps);
+
+        let a = thread::spawn({
+            let a_deps = dep_refs.clone();
+            move || a_deps.create(A("a".into())).unwrap()
+        });
+
+        let b = thread::spawn({
+            let b_deps = dep_refs.clone();
+            move || b_deps.create(B("b".into())).unwrap()
+        });
+
+        assert_eq!(b.join().unwrap().explode(), B("b".into()));
+        assert_eq!(a.join().unwrap().explode(), A("a".into()));
+    }
+
+    #[test]
+    fn can_create_bridge_dependency() {
+        let mut deps = Deps::new();
+
+        let created_bridge = Arc::new(Mutex::new(None));
+        let created_bridge_clone = created_bridge.clone(); // so we can modify this from inside the closure
+
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(vec![a.0.clone(), b.0.clone()]));
+
+        // Use this to copy created Vec<String> value from bridge to mutex protected clone
+        deps.when_ready(move |_: &Deps, parent: &mut Vec<String>| {
+            *created_bridge_clone.lock().unwrap() = Some(parent.clone());
+            Ok(())
+        });
+
+        // Bind to created A and modify the value from "Hello" to "Hi"
+        deps.attach(|_: &Deps, a: &mut A| {
+            *a = A("Hi".into());
+            Ok(5)
+        });
+
+        // Attach to any type Vec<String> and append "Nice" to last element
+        deps.attach(|_: &Deps, created_bridge_result: &mut Vec<String>| {
+            created_bridge_result.push("Nice".to_string());
+            Ok(())
+        });
+
+        // Create both instigators and result should appear
+        let mut a = deps.create(A("Hello".into())).unwrap();
+        let mut b = deps.create(B("World".into())).unwrap();
+
+        {
+            let al = a.lock().unwrap();
+            let bl = b.lock().unwrap();
+
+            assert_eq!("Hi", al.0);
+            assert_eq!("World", bl.0);
+        }
+
+        {
+            let val = created_bridge.lock()
+                .unwrap();
+            assert_eq!("HiWorldNice",
+                       val.as_ref().expect("expected bridge val to be created").concat());
+        }
+
+        let mut c = deps.create(B("Rust".into())).unwrap();
+
+        {
+            let cl = c.lock().unwrap();
+
+            assert_eq!("Rust", cl.0);
+        }
+
+        {
+            let val = created_bridge.lock()
+                .unwrap();
+            assert_eq!("HiRustNice",
+                       val.as_ref().expect("expected bridge val to be created").concat());
+        }
+
+        assert_eq!(c.explode(), B("Rust".into()));
+        assert_eq!(a.explode(), A("Hi".into()));
+        assert_eq!(b.explode(), B("World".into()));
+    }
+
+    #[test]
+    fn can_create_bridge_dependency_for_three_parents() {
+        let mut deps = Deps::new();
+
+        let created = Arc::new(Mutex::new(Vec::new()));
+
+        deps.bridge3({
+            let created = created.clone();
+            move |_: &Deps, a: &mut A, b: &mut B, c: &mut C| {
+                created.lock().unwrap().push([&a.0[..], &b.0[..], &c.0[..]].concat());
+                Ok(())
+            }
+        });
+
+        let a = deps.create(A("a".into())).unwrap();
+        let b = deps.create(B("b".into())).unwrap();
+        assert_eq!(0, created.lock().unwrap().len());
+
+        let c1 = deps.create(C("c1".into())).unwrap();
+        let c2 = deps.create(C("c2".into())).unwrap();
+        assert_eq!(vec!["abc1".to_string(), "abc2".to_string()],
+                   *created.lock().unwrap());
+
+        assert_eq!(c1.explode(), C("c1".into()));
+        assert_eq!(c2.explode(), C("c2".into()));
+        assert_eq!(a.explode(), A("a".into()));
+        assert_eq!(b.explode(), B("b".into()));
+    }
+
+    #[test]
+    fn destroys_bridge_dependency_when_any_parent_is_destroyed() {
+        struct Bridged(Arc<Mutex<usize>>);
+
+        impl Drop for Bridged {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() -= 1;
+            }
+        }
+
+        let mut deps = Deps::new();
+
+        let alive = Arc::new(Mutex::new(0));
+
+        deps.bridge3({
+            let alive = alive.clone();
+            move |_: &Deps, _: &mut A, _: &mut B, _: &mut C| {
+                *alive.lock().unwrap() += 1;
+                Ok(Bridged(alive.clone()))
+            }
+        });
+
+        let a = deps.create(A("a".into())).unwrap();
+        let b1 = deps.create(B("b1".into())).unwrap();
+        let b2 = deps.create(B("b2".into())).unwrap();
+        let c = deps.create(C("c".into())).unwrap();
+        assert_eq!(2, *alive.lock().unwrap());
+
+        // the first parent is destroyed, although the children were created by the last one
+        a.explode();
+        assert_eq!(0, *alive.lock().unwrap());
+
+        let a = deps.create(A("a".into())).unwrap();
+        assert_eq!(2, *alive.lock().unwrap());
+
+        b1.explode();
+        assert_eq!(1, *alive.lock().unwrap());
+
+        c.explode();
+        assert_eq!(0, *alive.lock().unwrap());
+
+        b2.explode();
+        a.explode();
+    }
+
+    #[test]
+    fn tears_down_bridge_dependency_when_any_parent_is_destroyed() {
+        let mut deps = Deps::new();
+
+        deps.bridge_with_teardown(|_: &Deps, a: &mut A, b: &mut B| {
+                                      a.0.push_str(&b.0);
+                                      Ok(b.0.len())
+                                  },
+                                  |a: &mut A, _: &mut B, len: &mut usize| {
+                                      let new_len = a.0.len() - *len;
+                                      a.0.truncate(new_len);
+                                  });
+
+        let mut a = deps.create(A("a".into())).unwrap();
+        let b1 = deps.create(B("b1".into())).unwrap();
+        let b2 = deps.create(B("b2".into())).unwrap();
+        assert_eq!("ab1b2", a.lock().unwrap().0);
+
+        b2.explode();
+        assert_eq!("ab1", a.lock().unwrap().0);
+
+        assert_eq!(a.explode(), A("a".into()));
+        assert_eq!(b1.explode(), B("b1".into()));
+    }
+
+    #[test]
+    fn creates_every_bridge_dependency_once_per_pair() {
+        let mut deps = Deps::new();
+
+        let created = Arc::new(Mutex::new(Vec::new()));
+
+        deps.bridge({
+            let created = created.clone();
+            move |_: &Deps, a: &mut A, b: &mut B| {
+                created.lock().unwrap().push(["first:", &a.0[..], &b.0[..]].concat());
+                Ok(())
+            }
+        });
+        deps.bridge({
+            let created = created.clone();
+            move |_: &Deps, a: &mut A, b: &mut B| {
+                created.lock().unwrap().push(["second:", &a.0[..], &b.0[..]].concat());
+                Ok(C([&a.0[..], &b.0[..]].concat()))
+            }
+        });
+
+        let created_c = Arc::new(Mutex::new(Vec::new()));
+        deps.when_ready({
+            let created_c = created_c.clone();
+            move |_: &Deps, c: &mut C| {
+                created_c.lock().unwrap().push(c.clone());
+                Ok(())
+            }
+        });
+
+        let _a1 = deps.create(A("a1".into())).unwrap();
+        let _b = deps.create(B("b".into())).unwrap();
+        let _a2 = deps.create(A("a2".into())).unwrap();
+
+        assert_eq!(vec!["first:a1b".to_string(),
+                        "second:a1b".to_string(),
+                        "first:a2b".to_string(),
+                        "second:a2b".to_string()],
+                   *created.lock().unwrap());
+        assert_eq!(vec![C("a1b".into()), C("a2b".into())],
+                   *created_c.lock().unwrap());
+    }
+
+    #[test]
+    fn destroys_children_in_reverse_order_and_gives_back_value_if_construction_fails() {
+        struct Logged(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.1.lock().unwrap().push(self.0);
+            }
+        }
+
+        let mut deps = Deps::new();
+
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+
+        deps.attach({
+            let dropped = dropped.clone();
+            move |_: &Deps, _: &mut A| Ok(Logged("first", dropped.clone()))
+        });
+        deps.attach({
+            let dropped = dropped.clone();
+            move |_: &Deps, _: &mut A| Ok(Logged("second", dropped.clone()))
+        });
+        deps.attach(|_: &Deps, a: &mut A| -> ::Result<()> {
+            a.0.push_str(" modified");
+            Err(Error::new("failed"))
+        });
+
+        let err = deps.try_create(A("a".into())).unwrap_err();
+
+        assert_eq!("failed", err.error().cause().to_string());
+        assert_eq!(A("a modified".into()), err.into_value());
+        assert_eq!(vec!["second", "first"], *dropped.lock().unwrap());
+    }
+
+    #[test]
+    fn unregisters_bridged_parent_if_construction_fails() {
+        let mut deps = Deps::new();
+
+        let created = Arc::new(Mutex::new(Vec::new()));
+
+        deps.bridge({
+            let created = created.clone();
+            move |_: &Deps, a: &mut A, b: &mut B| {
+                created.lock().unwrap().push([&a.0[..], &b.0[..]].concat());
+                Ok(())
+            }
+        });
+        deps.when_ready(|_: &Deps, b: &mut B| -> ::Result<()> {
+            if b.0 == "fail" {
+                return Err(Error::new("failed"));
+            }
+            Ok(())
+        });
+
+        let a = deps.create(A("a".into())).unwrap();
+        let err = deps.try_create(B("fail".into())).unwrap_err();
+        assert_eq!(B("fail".into()), err.into_value());
+
+        // failed parent is no longer bridged with new values
+        let _a2 = deps.create(A("a2".into())).unwrap();
+        assert_eq!(vec!["afail".to_string()], *created.lock().unwrap());
+
+        assert_eq!(a.explode(), A("a".into()));
+    }
+
+    #[test]
+    fn does_not_leave_parent_in_inceptor_if_bridge_fails() {
+        let mut deps = Deps::new();
+
+        deps.bridge(|_: &Deps, _: &mut A, b: &mut B| -> ::Result<()> {
+            if b.0 == "fail" {
+                return Err(Error::new("failed"));
+            }
+            Ok(())
+        });
+
+        let a = deps.create(A("a".into())).unwrap();
+        let err = deps.try_create(B("fail".into())).unwrap_err();
+        assert_eq!(B("fail".into()), err.into_value());
+
+        assert_eq!(a.explode(), A("a".into()));
+    }
+
+    #[test]
+    fn reports_construction_path_of_failed_attach() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.attach(|_: &Deps, _: &mut B| -> ::Result<C> { Err(Error::new("failed")) });
+
+        let err = deps.create(A("a".into())).unwrap_err();
+
+        assert_eq!(Some(ErrorKind::Attach), err.kind());
+        assert_eq!(&[any::type_name::<A>(), any::type_name::<B>(), any::type_name::<C>()],
+                   err.path());
+        assert_eq!("failed", err.cause().to_string());
+    }
+
+    #[test]
+    fn reports_construction_path_of_failed_when_ready() {
+        let mut deps = Deps::new();
+
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+        deps.when_ready(|_: &Deps, _: &mut C| -> ::Result<()> { Err(Error::new("failed")) });
+
+        let _a = deps.create(A("a".into())).unwrap();
+        let err = deps.create(B("b".into())).unwrap_err();
+
+        assert_eq!(Some(ErrorKind::WhenReady), err.kind());
+        assert_eq!(&[any::type_name::<B>(), any::type_name::<C>()], err.path());
+    }
+
+    #[test]
+    fn finds_attach_cycle() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.attach(|_: &Deps, b: &mut B| Ok(C(b.0.clone())));
+        assert!(deps.check_cycles().is_ok());
+
+        deps.attach(|_: &Deps, b: &mut B| Ok(A(b.0.clone())));
+        let err = deps.check_cycles().unwrap_err();
+
+        assert_eq!(Some(ErrorKind::Cycle), err.kind());
+        assert_eq!(&[any::type_name::<A>(), any::type_name::<B>(), any::type_name::<A>()],
+                   err.path());
+    }
+
+    #[test]
+    fn fails_to_create_attach_cycle() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.attach(|_: &Deps, b: &mut B| Ok(A(b.0.clone())));
+
+        let err = deps.create(A("a".into())).unwrap_err();
+
+        assert_eq!(Some(ErrorKind::Cycle), err.kind());
+        assert_eq!(128, err.path().len());
+        assert_eq!(format!("cyclic dependency {} -> {} -> {}",
+                           any::type_name::<A>(),
+                           any::type_name::<B>(),
+                           any::type_name::<A>()),
+                   err.cause().to_string());
+    }
+
+    #[test]
+    fn describes_registrations_in_graph() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.attach(|_: &Deps, _: &mut A| Ok(()));
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+        deps.collectable(|_: &Deps| A("a".into()));
+        deps.when_ready(|_: &Deps, _: &mut C| Ok(()));
+
+        let edges = deps.graph().edges();
+
+        assert_eq!(&[Edge::new(EdgeKind::Attach, vec![Node::of::<A>()], Some(Node::of::<B>())),
+                     Edge::new(EdgeKind::Attach, vec![Node::of::<A>()], None),
+                     Edge::new(EdgeKind::Bridge,
+                               vec![Node::of::<A>(), Node::of::<B>()],
+                               Some(Node::of::<C>())),
+                     Edge::new(EdgeKind::Collectable,
+                               vec![Node::of::<Collection<A>>()],
+                               Some(Node::of::<A>())),
+                     Edge::new(EdgeKind::WhenReady, vec![Node::of::<C>()], None)],
+                   edges);
+    }
+
+    #[test]
+    fn prints_tree_of_live_scope() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.attach(|_: &Deps, b: &mut B| Ok(C(b.0.clone())));
+        deps.bridge(|_: &Deps, a: &mut A, c: &mut C| Ok(vec![a.0.clone(), c.0.clone()]));
+
+        let _c = deps.create(C("c".into())).unwrap();
+        let a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!(format!("{a} [shared]
+  {b} [isolated]
+    {c} [shared]
+      bridge #1 of ({a}, {c})
+        {v} [isolated]
+  bridge #0 of ({a}, {c})
+    {v} [isolated]
+    {v} [isolated]
+",
+                           a = any::type_name::<A>(),
+                           b = any::type_name::<B>(),
+                           c = any::type_name::<C>(),
+                           v = any::type_name::<Vec<String>>()),
+                   a.tree());
+    }
+
+    #[test]
+    fn visits_live_scope() {
+        #[derive(Default)]
+        struct Counter {
+            scopes: Vec<(&'static str, Sharing)>,
+            bridges: usize,
+        }
+
+        impl Visitor for Counter {
+            fn enter_scope(&mut self, scope: &ScopeInfo) {
+                self.scopes.push((scope.name(), scope.sharing()));
+            }
+
+            fn enter_bridge(&mut self, bridge: &BridgeInfo) {
+                assert_eq!(Some(Node::of::<C>()), bridge.edge().child());
+                self.bridges += 1;
+            }
+        }
+
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+
+        let a = deps.create(A("a".into())).unwrap();
+        let mut counter = Counter::default();
+        a.inspect(&mut counter);
+
+        assert_eq!(vec![(any::type_name::<A>(), Sharing::Shared),
+                        (any::type_name::<B>(), Sharing::Shared),
+                        (any::type_name::<C>(), Sharing::Isolated),
+                        (any::type_name::<C>(), Sharing::Isolated)],
+                   counter.scopes);
+        assert_eq!(2, counter.bridges);
+    }
+
+    fn read<T: any::Any, R, F: Fn(&T) -> R>(scope: &Scope<T>, f: F) -> R {
+        match *scope.get_instance() {
+            Instance::Isolated(ref value) => f(value),
+            Instance::Shared(ref value) => f(&value.read().unwrap()),
+        }
+    }
+
+    #[test]
+    fn finds_typed_children() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone() + "b")));
+        deps.attach(|_: &Deps, b: &mut B| Ok(C(b.0.clone() + "c")));
+        deps.attach(|_: &Deps, a: &mut A| Ok(C(a.0.clone() + "c")));
+
+        let mut a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!("ab", read(&a.child::<B>().unwrap(), |b| b.0.clone()));
+        let found: Vec<String> = a.children::<C>().iter().map(|c| read(c, |c| c.0.clone())).collect();
+        assert_eq!(vec!["abc".to_string(), "ac".to_string()], found);
+        assert!(a.child::<A>().is_none());
+
+        a.lock_child::<B>().unwrap().unwrap().0 = "x".into();
+        for mut c in a.children_mut::<C>() {
+            c.lock().unwrap().0.push('!');
+        }
+
+        let mut b = a.child_mut::<B>().unwrap();
+        assert_eq!("x", b.lock().unwrap().0);
+        assert_eq!("abc!", b.child_mut::<C>().unwrap().lock().unwrap().0);
+    }
+
+    #[test]
+    fn finds_bridged_children() {
+        let mut deps = Deps::new();
+
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+
+        let _b1 = deps.create(B("1".into())).unwrap();
+        let _b2 = deps.create(B("2".into())).unwrap();
+        let mut a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!("a1", read(&a.child::<C>().unwrap(), |c| c.0.clone()));
+        let found: Vec<_> = a.children::<C>().iter().map(|c| read(c, |c| c.0.clone())).collect();
+        assert_eq!(vec!["a1".to_string(), "a2".to_string()], found);
+
+        a.lock_child::<C>().unwrap().unwrap().0.push('!');
+        for mut c in a.children_mut::<C>() {
+            c.lock().unwrap().0.push('?');
+        }
+        {
+            let first = a.child::<C>().unwrap();
+            assert_eq!("a1!?", read(&first, |c| c.0.clone()));
+            // This thread keeps the first bridged child locked, so lookups skip it.
+            assert_eq!("a2?", read(&a.child::<C>().unwrap(), |c| c.0.clone()));
+        }
+        assert_eq!("a1!?", a.child_mut::<C>().unwrap().lock().unwrap().0);
+
+        let mut found = Vec::new();
+        a.with_children(|c: &mut Scope<C>| found.push(c.lock().unwrap().0.clone()));
+        assert_eq!(vec!["a1!?".to_string(), "a2?".to_string()], found);
+    }
+
+    fn shared<T: any::Any>(scope: &Scope<T>) -> Shared<T> {
+        match *scope.get_instance() {
+            Instance::Shared(ref value) => value.clone(),
+            Instance::Isolated(_) => panic!("expected shared instance"),
+        }
+    }
+
+    #[test]
+    fn gives_back_scope_if_value_is_still_shared() {
+        let mut deps = Deps::new();
+
+        deps.attach(|_: &Deps, a: &mut A| Ok(C(a.0.clone())));
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+
+        let _b = deps.create(B("b".into())).unwrap();
+        let a = deps.create(A("a".into())).unwrap();
+        let tree = a.tree();
+        let leaked = shared(&a);
+
+        let e = a.try_explode().unwrap_err();
+        assert_eq!(ExplodeReason::Shared, e.reason());
+        let a = e.into_scope();
+        assert_eq!(tree, a.tree());
+
+        drop(leaked);
+        assert_eq!(A("a".into()), a.try_explode().unwrap());
+    }
+
+    #[test]
+    fn gives_back_scope_if_value_is_poisoned() {
+        let mut deps = Deps::new();
+
+        deps.bridge(|_: &Deps, _: &mut A, _: &mut B| Ok(()));
+
+        let a = deps.create(A("a".into())).unwrap();
+        let value = match shared(&a) {
+            Shared::Mutex(value) => value,
+            _ => panic!("expected value shared in mutex"),
+        };
+        thread::spawn(move || {
+            let _guard = value.lock().unwrap();
+            panic!("poison");
+        }).join().unwrap_err();
+
+        let e = a.try_explode().unwrap_err();
+        assert_eq!(ExplodeReason::Poisoned, e.reason());
+        assert!(e.into_scope().lock().is_err());
+    }
+
+    #[test]
+    fn gives_back_scope_while_another_thread_bridges_its_value() {
+        let mut deps = Deps::new();
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| {
+            thread::sleep(Duration::from_millis(300));
+            Ok(C([&a.0[..], &b.0[..]].concat()))
+        });
+        let a = deps.create(A("a".into())).unwrap();
+
+        let (a, mut b) = thread::scope(|s| {
+            let b = s.spawn(|| deps.create(B("b".into())).unwrap());
+            thread::sleep(Duration::from_millis(100));
+            let e = a.try_explode().unwrap_err();
+            assert_eq!(ExplodeReason::Shared, e.reason());
+            (e.into_scope(), b.join().unwrap())
+        });
+        assert_eq!(1, b.children::<C>().len());
+
+        assert_eq!(A("a".into()), a.explode());
+        let mut bridged = Vec::new();
+        b.with_children(|c: &mut Scope<C>| bridged.push(c.lock().unwrap().0.clone()));
+        assert!(bridged.is_empty());
+    }
+
+    #[test]
+    fn drops_parent_while_another_thread_waits_to_bridge_it() {
+        let mut deps = Deps::new();
+        deps.bridge3(|_: &Deps, a: &mut A, b: &mut B, c: &mut C| Ok([&a.0[..], &b.0[..], &c.0[..]].concat()));
+        let mut a = deps.create(A("a".into())).unwrap();
+        let b = deps.create(B("b".into())).unwrap();
+
+        let c = thread::scope(|s| {
+            let guard = a.lock().unwrap();
+            let c = s.spawn(|| deps.create(C("c".into())).unwrap());
+            // The other thread waits for `a` while bridging it with `b`.
+            thread::sleep(Duration::from_millis(100));
+            drop(b);
+            drop(guard);
+            c.join().unwrap()
+        });
+
+        assert!(c.children::<String>().is_empty());
+        assert_eq!(A("a".into()), a.explode());
+    }
+
+    struct Document(&'static str);
+    struct Editor(&'static str);
+    struct StatusBar(&'static str);
+
+    #[test]
+    fn bridges_within_same_scope() {
+        let mut deps = Deps::new();
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+
+        deps.attach(|_: &Deps, d: &mut Document| Ok(Editor(d.0)));
+        deps.attach(|_: &Deps, d: &mut Document| Ok(StatusBar(d.0)));
+        {
+            let bridged = bridged.clone();
+            deps.bridge_within::<Document, _, _, _, _>(move |_: &Deps, e: &mut Editor, s: &mut StatusBar| {
+                bridged.lock().unwrap().push((e.0, s.0));
+                Ok(())
+            });
+        }
+
+        let _first = deps.create(Document("first")).unwrap();
+        let _second = deps.create(Document("second")).unwrap();
+        let _editor = deps.create(Editor("loose")).unwrap();
+        let _status = deps.create(StatusBar("loose")).unwrap();
+
+        assert_eq!(vec![("first", "first"), ("second", "second"), ("loose", "loose")],
+                   *bridged.lock().unwrap());
+    }
+
+    #[test]
+    fn bridges_within_scope_parent_itself() {
+        let mut deps = Deps::new();
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+
+        deps.attach(|_: &Deps, d: &mut Document| Ok(Editor(d.0)));
+        {
+            let bridged = bridged.clone();
+            deps.bridge_within::<Document, _, _, _, _>(move |_: &Deps, d: &mut Document, e: &mut Editor| {
+                bridged.lock().unwrap().push((d.0, e.0));
+                Ok(())
+            });
+        }
+
+        let _first = deps.create(Document("first")).unwrap();
+        let _second = deps.create(Document("second")).unwrap();
+
+        assert_eq!(vec![("first", "first"), ("second", "second")], *bridged.lock().unwrap());
+    }
+
+    struct Window(u32);
+    struct Layer(u32, &'static str);
+
+    #[test]
+    fn bridges_by_key() {
+        let mut deps = Deps::new();
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let bridged = bridged.clone();
+            deps.bridge_by_key(|w: &Window| w.0, |l: &Layer| l.0, move |_: &Deps, w: &mut Window, l: &mut Layer| {
+                bridged.lock().unwrap().push((w.0, l.1));
+                Ok(())
+            });
+        }
+
+        let _background = deps.create(Layer(1, "background")).unwrap();
+        let _first = deps.create(Window(1)).unwrap();
+        let second = deps.create(Window(2)).unwrap();
+        let _overlay = deps.create(Layer(2, "overlay")).unwrap();
+        let _text = deps.create(Layer(1, "text")).unwrap();
+        drop(second);
+        let _dialog = deps.create(Layer(2, "dialog")).unwrap();
+
+        assert_eq!(vec![(1, "background"), (2, "overlay"), (1, "text")], *bridged.lock().unwrap());
+    }
+
+    struct Feature(&'static str, bool);
+
+    #[test]
+    fn bridges_only_accepted_pairs() {
+        let mut deps = Deps::new();
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let bridged = bridged.clone();
+            deps.bridge_filtered(|w: &Window, f: &Feature| f.1 || w.0 == 0,
+                                 move |_: &Deps, w: &mut Window, f: &mut Feature| {
+                                     bridged.lock().unwrap().push((w.0, f.0));
+                                     Ok(())
+                                 });
+        }
+
+        let _enabled = deps.create(Feature("enabled", true)).unwrap();
+        let _disabled = deps.create(Feature("disabled", false)).unwrap();
+        let _main = deps.create(Window(0)).unwrap();
+        let _other = deps.create(Window(1)).unwrap();
+
+        assert_eq!(vec![(0, "enabled"), (0, "disabled"), (1, "enabled")], *bridged.lock().unwrap());
+    }
+
+    #[test]
+    fn bridge_constructor_can_use_deps() {
+        let mut deps = Deps::new();
+
+        deps.collectable(|_: &Deps| "collected".to_string());
+        deps.attach(|_: &Deps, c: &mut C| Ok(Window(c.0.len() as u32)));
+        deps.bridge(|deps: &Deps, a: &mut A, b: &mut B| {
+            let collected: Vec<String> = deps.collect::<String>()?.iter().cloned().collect();
+            deps.create(C([&a.0[..], &b.0[..], &collected.concat()[..]].concat()))
+        });
+
+        let _b = deps.create(B("b".into())).unwrap();
+        let mut a = deps.create(A("a".into())).unwrap();
+
+        let mut found = Vec::new();
+        a.with_children(|c: &mut Scope<Scope<C>>| {
+            let mut c = c.lock().unwrap();
+            found.push(c.lock().unwrap().0.clone());
+            found.extend(c.child_mut::<Window>().map(|mut w| w.lock().unwrap().0.to_string()));
+        });
+        assert_eq!(vec!["abcollected".to_string(), "11".to_string()], found);
+    }
+
+    #[test]
+    fn bridges_every_pair_of_peers_once() {
+        let mut deps = Deps::new();
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let bridged = bridged.clone();
+            deps.bridge_peers(move |_: &Deps, a: &mut Window, b: &mut Window| {
+                bridged.lock().unwrap().push((a.0, b.0));
+                Ok(C(format!("{}-{}", a.0, b.0)))
+            });
+        }
+
+        let _first = deps.create(Window(1)).unwrap();
+        let mut second = deps.create(Window(2)).unwrap();
+        let third = deps.create(Window(3)).unwrap();
+
+        assert_eq!(vec![(1, 2), (1, 3), (2, 3)], *bridged.lock().unwrap());
+
+        drop(third);
+        let mut found = Vec::new();
+        second.with_children(|c: &mut Scope<C>| found.push(c.lock().unwrap().0.clone()));
+        assert_eq!(vec!["1-2".to_string()], found);
+    }
+
+    #[test]
+    fn rejects_bridge_of_same_type_that_is_not_peers() {
+        fn rejected<F: FnOnce(&mut Deps)>(register: F) -> String {
+            let mut deps = Deps::new();
+            let e = panic::catch_unwind(panic::AssertUnwindSafe(|| register(&mut deps))).unwrap_err();
+            e.downcast::<String>().map(|message| *message).unwrap_or_default()
+        }
+
+        let messages = vec![
+            rejected(|deps| deps.bridge(|_: &Deps, _: &mut Window, _: &mut Window| Ok(()))),
+            rejected(|deps| deps.bridge_within::<A, _, _, _, _>(|_: &Deps, _: &mut Window, _: &mut Window| Ok(()))),
+            rejected(|deps| deps.bridge_by_key(|w: &Window| w.0, |w: &Window| w.0, |_: &Deps, _: &mut Window, _: &mut Window| Ok(()))),
+            rejected(|deps| deps.bridge_filtered(|_: &Window, _: &Window| true, |_: &Deps, _: &mut Window, _: &mut Window| Ok(()))),
+            rejected(|deps| deps.bridge_with_teardown(|_: &Deps, _: &mut Window, _: &mut Window| Ok(()), |_: &mut Window, _: &mut Window, _: &mut ()| {})),
+            rejected(|deps| deps.bridge3(|_: &Deps, _: &mut A, _: &mut Window, _: &mut Window| Ok(()))),
+        ];
+
+        for message in messages {
+            assert_eq!(format!("bridge has parent {} at more than one position, use bridge_peers to bridge values \
+                                of the same type",
+                               any::type_name::<Window>()),
+                       message);
+        }
+    }
+
+    #[test]
+    fn tears_down_bridged_child_after_visit_that_drops_its_parent() {
+        let mut deps = Deps::new();
+        let log = Log::default();
+        {
+            let log = log.clone();
+            deps.bridge_with_teardown(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())),
+                                      move |_: &mut A, _: &mut B, c: &mut C| {
+                                          log.lock().unwrap().push(format!("teardown {}", c.0))
+                                      });
+        }
+        let mut a = deps.create(A("a".into())).unwrap();
+        let mut b = Some(deps.create(B("b".into())).unwrap());
+
+        a.with_children(|c: &mut Scope<C>| {
+            drop(b.take());
+            log.lock().unwrap().push(format!("visited {}", c.lock().unwrap().0));
+        });
+
+        assert_eq!(vec!["visited ab", "teardown ab"], *log.lock().unwrap());
+        let mut found = 0;
+        a.with_children(|_: &mut Scope<C>| found += 1);
+        assert_eq!(0, found);
+    }
+
+    fn recording_bridge(deps: &mut Deps) -> Arc<Mutex<Vec<(String, String)>>> {
+        let bridged = Arc::new(Mutex::new(Vec::new()));
+        let recorded = bridged.clone();
+        deps.bridge(move |_: &Deps, a: &mut A, b: &mut B| {
+            recorded.lock().unwrap().push((a.0.clone(), b.0.clone()));
+            Ok(C([&a.0[..], &b.0[..]].concat()))
+        });
+        bridged
+    }
+
+    #[test]
+    fn creates_bridged_parent_inside_bridge_constructor() {
+        let mut deps = Deps::new();
+        let bridged = recording_bridge(&mut deps);
+        deps.bridge(|deps: &Deps, a: &mut A, _: &mut B| -> ::Result<Vec<Scope<A>>> {
+            if a.0 == "a" {
+                return Ok(vec![deps.create(A("nested".into()))?]);
+            }
+            Ok(Vec::new())
+        });
+
+        let _b = deps.create(B("b".into())).unwrap();
+        let _a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!(vec![("a".to_string(), "b".to_string()), ("nested".to_string(), "b".to_string())],
+                   *bridged.lock().unwrap());
+    }
+
+    #[test]
+    fn creates_bridged_parent_inside_bridged_child() {
+        let mut deps = Deps::new();
+        let bridged = recording_bridge(&mut deps);
+        deps.attach(|deps: &Deps, c: &mut C| {
+            if c.0 == "ab" {
+                return Ok(Some(deps.create(A("child".into()))?));
+            }
+            Ok(None)
+        });
+
+        let _b = deps.create(B("b".into())).unwrap();
+        let _a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!(vec![("a".to_string(), "b".to_string()), ("child".to_string(), "b".to_string())],
+                   *bridged.lock().unwrap());
+    }
+
+    #[test]
+    fn creates_bridged_parent_when_ready() {
+        let mut deps = Deps::new();
+        let bridged = recording_bridge(&mut deps);
+        let created = Arc::new(Mutex::new(Vec::new()));
+        {
+            let created = created.clone();
+            deps.when_ready(move |deps: &Deps, a: &mut A| {
+                created.lock().unwrap().push(deps.create(B([&a.0[..], "'s b"].concat()))?);
+                Ok(())
+            });
+        }
+
+        let _a = deps.create(A("a".into())).unwrap();
 
-        // Create inceptor clone for P2 instances
-        let inceptor_2 = inceptor_1.clone();
-
-        self.register_shared_constructor::<P1>(
-            into_shared_constructor::<P1, P2, C>(
-                inceptor_1,
-                Box::new(|inceptor: &Arc<Mutex<Inceptor<P1, P2>>>, parent: &mut Box<Any>|
-                {
-                    let parent_for_inceptor = parent.downcast_mut::<Arc<Mutex<P1>>>()
-                        .expect("expected downcast P1")
-                        .clone();
-                    inceptor.lock()
-                        .expect("failed to lock ic1")
-                        .incept_1(parent_for_inceptor)
-                }),
-                1
-            )
-        );
-        self.register_shared_constructor::<P2>(
-            into_shared_constructor::<P1, P2, C>(
-                inceptor_2,
-                Box::new(|inceptor: &Arc<Mutex<Inceptor<P1, P2>>>, parent: &mut Box<Any>|
-                {
-                    let parent_for_inceptor = parent.downcast_mut::<Arc<Mutex<P2>>>()
-                        .expect("expected downcast P2")
-                        .clone();
-                    inceptor.lock()
-                        .expect("failed to lock ic2")
-                        .incept_2(parent_for_inceptor)
-                }),
-                2
-            )
-        );
+        assert_eq!(vec![("a".to_string(), "a's b".to_string())], *bridged.lock().unwrap());
     }
 
-    pub fn collectable<C, F>(&mut self, constructor: F)
-        where C: 'static + Any,
-              F: for<'r> Fn(&Deps) -> C + 'static + Send + Sync
-    {
-        self.register_isolated_constructor::<Collection<C>>(
-            into_isolated_constructor_without_child_deps(move |deps: &Deps, parent: &mut Collection<C>| {
-                parent.push(constructor(deps))
-            })
-        );
+    #[test]
+    fn discards_bridges_deferred_by_failed_creation() {
+        let mut deps = Deps::new();
+        let bridged = recording_bridge(&mut deps);
+        deps.when_ready(|deps: &Deps, _: &mut A| -> ::Result<()> {
+            let _b = deps.create(B("b".into()))?;
+            Err(Error::new("failed"))
+        });
+
+        let e = deps.try_create(A("a".into())).unwrap_err();
+
+        assert_eq!(Some(ErrorKind::WhenReady), e.error().kind());
+        assert_eq!("a", e.into_value().0);
+        assert!(bridged.lock().unwrap().is_empty());
+
+        let _b = deps.create(B("later".into())).unwrap();
+        assert!(bridged.lock().unwrap().is_empty());
     }
 
-    fn create_deps_for_any_parent<F>(&self,
-                                     type_id: TypeId,
-                                     mut parent_not_shared: Box<Any>,
-                                     to_shared: F)
-                                     -> Result<(AnyInstance, Vec<Box<Any>>)>
-        where F: Fn(Box<Any>) -> Box<Any>
-    {
-        let mut deps = Vec::new();
+    #[test]
+    fn defers_bridges_with_values_locked_by_user_until_they_are_unlocked() {
+        let mut deps = Deps::new();
 
-        // First, construct any instances that do not need parent wrapped in mutex
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
 
-        match self.isolated_constructors.get(&type_id) {
-            Some(isolated_list) => {
-                for any_constructor in isolated_list {
-                    match any_constructor(&self, &mut parent_not_shared) {
-                        Ok(Constructed { children }) => deps.extend(children),
-                        Err(any_err) => return Err(any_err),
-                    };
-                }
+        let mut b = deps.create(B("1".into())).unwrap();
+        let a = {
+            let _g = b.lock();
+            let a = deps.create(A("a".into())).unwrap();
+            assert!(a.child::<C>().is_none());
+            a
+        };
+        assert_eq!("a1", read(&a.child::<C>().unwrap(), |c| c.0.clone()));
+
+        let guard = a.read().unwrap();
+        let _b2 = deps.create(B("2".into())).unwrap();
+        assert_eq!(1, a.children::<C>().len());
+        guard.unlock().unwrap();
+
+        let found: Vec<_> = a.children::<C>().iter().map(|c| read(c, |c| c.0.clone())).collect();
+        assert_eq!(vec!["a1".to_string(), "a2".to_string()], found);
+    }
+
+    #[test]
+    fn creates_every_deferred_bridge_even_if_some_of_them_fail() {
+        let mut deps = Deps::new();
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| {
+            if b.0.starts_with("bad") {
+                Err(Error::new(format!("refused {}", b.0)))
+            } else {
+                Ok(C([&a.0[..], &b.0[..]].concat()))
             }
-            None => (),
-        }
+        });
 
-        // Then, check if there are shared constructors, and if so, wrap value in mutex
-        // and return it in AnyInstance::Shared, otherwise, return it in AnyInstance::Isolated.
+        let mut a = deps.create(A("a".into())).unwrap();
+        let guard = a.lock().unwrap();
+        let b1 = deps.create(B("1".into())).unwrap();
+        let bad1 = deps.create(B("bad1".into())).unwrap();
+        let bad2 = deps.create(B("bad2".into())).unwrap();
+        let e = guard.unlock().unwrap_err();
 
-        let mut parent_result = match self.shared_constructors.get(&type_id) {
-            Some(shared_list) => {
-                let mut parent_shared = to_shared(parent_not_shared);
+        assert_eq!(Some(ErrorKind::Bridge), e.kind());
+        assert_eq!(1, e.others().len());
+        assert_eq!("a1", read(&a.child::<C>().unwrap(), |c| c.0.clone()));
+        assert_eq!(1, a.children::<C>().len());
 
-                for any_constructor in shared_list {
-                    match any_constructor(&self, &mut parent_shared) {
-                        Ok(ConstructedShared { children }) => deps.extend(children),
-                        Err(any_err) => return Err(any_err),
-                    };
-                }
+        {
+            let _guard = a.lock().unwrap();
+            let _bad3 = deps.create(B("bad3".into())).unwrap();
+        }
+        deps.attach(|_: &Deps, _: &mut C| Ok(()));
 
-                AnyInstance::Shared(parent_shared)
-            }
-            None => AnyInstance::Isolated(parent_not_shared),
-        };
+        mem::drop((bad1, bad2));
+        assert_eq!("1", b1.try_explode().unwrap().0);
+        assert_eq!("a", a.try_explode().unwrap().0);
+    }
 
-        // Execute post create actions for the value
+    #[test]
+    fn creates_deferred_bridges_with_registrations_they_were_deferred_with() {
+        let mut deps = Deps::new();
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+        let ready = Arc::new(Mutex::new(Vec::new()));
 
-        if let Some(actions) = self.type_scope_created.get(&type_id) {
-            for action in actions {
-                try!(action(&self, &mut parent_result));
-            }
+        let mut a = deps.create(A("a".into())).unwrap();
+        let guard = a.lock().unwrap();
+        let _b1 = deps.create(B("1".into())).unwrap();
+        {
+            let ready = ready.clone();
+            deps.when_ready(move |_: &Deps, c: &mut C| {
+                ready.lock().unwrap().push(c.0.clone());
+                Ok(())
+            });
         }
+        guard.unlock().unwrap();
+        let _b2 = deps.create(B("2".into())).unwrap();
 
-        Ok((parent_result, deps))
+        assert_eq!(2, a.children::<C>().len());
+        assert_eq!(vec!["a2".to_string()], *ready.lock().unwrap());
     }
 
-    /// Register child constructor that will be invoked when the parent `P` type is
-    /// created.
-    fn register_isolated_constructor<P: Any>(&mut self,
-                                             any_constructor: Box<Fn(&Deps, &mut Box<Any>)
-                                                                     -> Result<Constructed> + Send + Sync>) {
-        match self.isolated_constructors.entry(TypeId::of::<P>()) {
-            Entry::Occupied(mut list) => {
-                list.get_mut().push(any_constructor);
-            }
-            Entry::Vacant(e) => {
-                e.insert(vec![any_constructor]);
-            }
+    struct Pen;
+    struct Paper;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn reports_shared_values_locked_in_both_orders() {
+        let mut deps = Deps::new();
+        deps.bridge(|_: &Deps, _: &mut Pen, _: &mut Paper| Ok(()));
+        let mut pen = deps.create(Pen).unwrap();
+        let mut paper = deps.create(Paper).unwrap();
+
+        let inverted = |i: &LockInversion| {
+            let names = [any::type_name::<Pen>(), any::type_name::<Paper>()];
+            names.contains(&i.first()) && names.contains(&i.second())
         };
+        {
+            let _pen = pen.lock().unwrap();
+            let _paper = paper.lock().unwrap();
+        }
+        {
+            let _paper = paper.lock().unwrap();
+            let _pen = pen.lock().unwrap();
+        }
+        assert_eq!(1, LockInversion::observed().iter().filter(|i| inverted(i)).count());
     }
 
-    /// Register child constructor that will be invoked when the parent `P` type is
-    /// created.
-    fn register_shared_constructor<P: Any>(&mut self,
-                                           any_constructor: Box<Fn(&Deps, &mut Box<Any>)
-                                                                   -> Result<ConstructedShared> + Send + Sync>) {
-        match self.shared_constructors.entry(TypeId::of::<P>()) {
-            Entry::Occupied(mut list) => {
-                list.get_mut().push(any_constructor);
-            }
-            Entry::Vacant(e) => {
-                e.insert(vec![any_constructor]);
-            }
-        };
+    #[test]
+    fn reads_value_shared_in_rwlock_from_many_threads() {
+        let mut deps = Deps::new();
+        deps.set_shared_lock_of::<A>(SharedLock::RwLock);
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+
+        let mut a = deps.create(A("a".into())).unwrap();
+        a.write().unwrap().0.push('!');
+        let _b = deps.create(B("b".into())).unwrap();
+
+        let reading = a.read().unwrap();
+        let read = thread::scope(|s| s.spawn(|| a.read().unwrap().0.clone()).join().unwrap());
+        assert_eq!("a!", read);
+        drop(reading);
+
+        let mut bridged = Vec::new();
+        a.with_children(|c: &mut Scope<C>| bridged.push(c.lock().unwrap().0.clone()));
+        assert_eq!(vec!["a!b".to_string()], bridged);
+        assert_eq!(A("a!".into()), a.try_explode().unwrap());
     }
-}
 
-unsafe impl Send for Deps {}
-unsafe impl Sync for Deps {}
+    #[test]
+    fn shares_values_in_lock_set_for_deps_unless_set_for_type() {
+        let mut deps = Deps::new();
+        deps.set_shared_lock(SharedLock::RwLock);
+        deps.set_shared_lock_of::<B>(SharedLock::Mutex);
+        deps.bridge(|_: &Deps, _: &mut A, _: &mut B| Ok(()));
 
-fn into_action_with_deps<P, F>(action: F)
-                               -> Box<Fn(&Deps, &mut AnyInstance) -> Result<()> + Send + Sync>
-    where F: for<'r> Fn(&Deps, &mut P) -> Result<()> + 'static + Send + Sync,
-          P: 'static + Any
-{
-    Box::new(move |deps: &Deps, parent: &mut AnyInstance| -> Result<()> {
-        match *parent {
-            AnyInstance::Isolated(ref mut value) => {
-                try!(action(deps,
-                            &mut value.downcast_mut::<P>()
-                                .expect("expected to downcast type in post create action")))
-            }
-            AnyInstance::Shared(ref mut value) => {
-                try!(action(deps,
-                            &mut value.downcast_mut::<Arc<Mutex<P>>>()
-                                .expect("expected to downcast type in post create action")
-                                .lock()
-                                .expect("expected to lock value for AnyInstance::Shared action")))
-            }
-        };
-        Ok(())
-    })
-}
+        let a = deps.create(A("a".into())).unwrap();
+        let b = deps.create(B("b".into())).unwrap();
 
-fn into_shared_constructor<P1, P2, C>
-    (
-        inceptor: Arc<Mutex<Inceptor<P1, P2>>>,
-        incept_fun: Box<Fn(&Arc<Mutex<Inceptor<P1, P2>>>, &mut Box<Any>) -> Result<(usize, Vec<Box<Any>>)> + Send + Sync>,
-        index: usize
-    )
-     -> Box<Fn(&Deps, &mut Box<Any>) -> Result<ConstructedShared> + Send + Sync>
-    where P1: 'static + Any + Send + Sync, // Parent 1
-          P2: 'static + Any + Send + Sync, // Parent 2
-          C: 'static + Any // Child
-{
-    Box::new(move |deps: &Deps, parent: &mut Box<Any>| -> Result<ConstructedShared> {
-        let (id, instances) = try!(incept_fun(&inceptor, parent));
+        assert_eq!(SharedLock::RwLock, shared(&a).lock_kind());
+        assert_eq!(SharedLock::Mutex, shared(&b).lock_kind());
+    }
 
-        let mut children: Vec<Box<Any>> = Vec::with_capacity(instances.len() + 1);
+    #[test]
+    fn runs_before_create_hooks_before_attached_constructors() {
+        let mut deps = Deps::new();
 
-        for instance in instances {
-            let instance_artifacts =
-                try!(deps.create_deps_for_any_parent(TypeId::of::<C>(), instance, to_shared::<C>));
-            children.push(Box::new(instance_artifacts));
-        }
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.before_create(|_: &Deps, a: &mut A| a.0.push('1'));
+        deps.before_create(|_: &Deps, a: &mut A| a.0.push('2'));
 
-        children.push(Box::new(Destructor::new(inceptor.clone(), index, id)));
+        let a = deps.create(A("a".into())).unwrap();
 
-        Ok(ConstructedShared { children: children })
-    })
-}
+        assert_eq!("a12", read(&a.child::<B>().unwrap(), |b| b.0.clone()));
+        assert_eq!(A("a12".into()), a.explode());
+    }
 
-fn into_isolated_constructor_with_child_deps<P, C, F>
-    (constructor: F)
-     -> Box<Fn(&Deps, &mut Box<Any>) -> Result<Constructed> + Send + Sync>
-    where F: for<'r> Fn(&Deps, &mut P) -> Result<C> + 'static + Send + Sync,
-          P: 'static + Any,
-          C: 'static + Any
-{
-    Box::new(move |deps: &Deps, parent: &mut Box<Any>| -> Result<Constructed> {
-        let child = {
-            let concrete_parent = parent.downcast_mut::<P>()
-                .expect("expected to downcast type in into_isolated_constructor_with_child_deps");
-            try!(deps.create(try!(constructor(deps, concrete_parent))))
-        };
-        Ok(Constructed { children: vec![Box::new(child)] })
-    })
-}
+    type Log = Arc<Mutex<Vec<String>>>;
 
-fn into_isolated_constructor_with_ignored_child_deps<P, C, F>
-    (constructor: F)
-     -> Box<Fn(&Deps, &mut Box<Any>) -> Result<Constructed> + Send + Sync>
-    where F: for<'r> Fn(&Deps, &mut P) -> Result<C> + 'static + Send + Sync,
-          P: 'static + Any,
-          C: 'static + Any
-{
-    Box::new(move |deps: &Deps, parent: &mut Box<Any>| -> Result<Constructed> {
-        try!(constructor(deps,
-                         parent.downcast_mut::<P>()
-                             .expect("expected to downcast type in \
-                                      into_isolated_constructor_with_ignored_child_deps")));
-        Ok(Constructed { children: vec![] })
-    })
-}
+    #[derive(Debug)]
+    struct Socket(Log);
+    struct Connection(Log);
 
-fn into_isolated_constructor_without_child_deps<P, F>
-    (constructor: F)
-     -> Box<Fn(&Deps, &mut Box<Any>) -> Result<Constructed> + Send + Sync>
-    where F: for<'r> Fn(&Deps, &mut P) + 'static + Send + Sync,
-          P: 'static + Any
-{
-    Box::new(move |deps: &Deps, parent: &mut Box<Any>| -> Result<Constructed> {
-        constructor(deps,
-                    parent.downcast_mut::<P>()
-                        .expect("expected to downcast type in \
-                                 into_isolated_constructor_without_child_deps"));
-        Ok(Constructed { children: vec![] })
-    })
-}
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().push("connection closed".into());
+        }
+    }
 
-#[cfg(test)]
-mod test {
-    use Deps;
-    use std::thread;
-    use std::sync::{Arc, Mutex};
+    fn socket_deps() -> Deps {
+        let mut deps = Deps::new();
+        deps.attach(|_: &Deps, socket: &mut Socket| Ok(Connection(socket.0.clone())));
+        deps.when_dropping(|socket: &mut Socket| socket.0.lock().unwrap().push("dropping".into()));
+        deps.when_dropped(|socket: &mut Socket| socket.0.lock().unwrap().push("dropped".into()));
+        deps
+    }
 
-    #[derive(Clone, Debug, Eq, PartialEq)]
-    struct A(String);
+    #[test]
+    fn runs_drop_hooks_around_destroying_children() {
+        let deps = socket_deps();
+        let log = Log::default();
 
-    #[derive(Clone, Debug, Eq, PartialEq)]
-    struct B(String);
+        drop(deps.create(Socket(log.clone())).unwrap());
 
-    #[derive(Clone, Debug, Eq, PartialEq)]
-    struct C(String);
+        assert_eq!(vec!["dropping", "connection closed", "dropped"], *log.lock().unwrap());
+    }
 
     #[test]
-    fn creates_dependency() {
-        let mut deps = Deps::new();
-
-        // here we want to know what is the state of dependency in closure, hence
-        // shared mutable reference to it
-        let created_b_ref = Arc::new(Mutex::new(None));
+    fn runs_drop_hooks_once_when_scope_is_exploded() {
+        let mut deps = socket_deps();
+        deps.bridge(|_: &Deps, _: &mut Socket, _: &mut B| Ok(()));
+        let log = Log::default();
 
-        deps.attach({
-            let created_b_ref = created_b_ref.clone();
-            move |_: &Deps, a: &mut A| {
-                let b = B([&a.0[..], "+B"].concat());
-                *created_b_ref.lock().unwrap() = Some(b.clone());
-                Ok(b)
-            }
-        });
+        let socket = deps.create(Socket(log.clone())).unwrap();
+        let leaked = shared(&socket);
+        let socket = socket.try_explode().unwrap_err().into_scope();
+        assert!(log.lock().unwrap().is_empty());
 
-        deps.create(A("Hello".into())).unwrap();
+        drop(leaked);
+        socket.explode();
 
-        assert_eq!("Hello+B",
-                   (*created_b_ref.lock().unwrap()).clone().unwrap().0);
+        assert_eq!(vec!["dropping", "connection closed", "dropped"], *log.lock().unwrap());
     }
 
     #[test]
-    fn creates_dependency_of_dependency() {
+    fn runs_drop_hooks_of_bridged_children() {
         let mut deps = Deps::new();
+        let log = Log::default();
+        deps.bridge(|_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())));
+        {
+            let log = log.clone();
+            deps.when_dropping(move |c: &mut C| log.lock().unwrap().push(c.0.clone()));
+        }
 
-        // here we want to know what is the state of dependency in closure, hence
-        // shared mutable reference to it
-        let created_c_ref = Arc::new(Mutex::new(None));
+        let b = deps.create(B("b".into())).unwrap();
+        let a = deps.create(A("a".into())).unwrap();
+        drop(b);
 
-        deps.attach(|_: &Deps, a: &mut A| Ok(B([&a.0[..], "+B"].concat())));
+        assert_eq!(vec!["ab"], *log.lock().unwrap());
+        drop(a);
+    }
 
-        deps.attach({
-            let created_c_ref = created_c_ref.clone();
-            move |_: &Deps, b: &mut B| {
-                let c = C([&b.0[..], "+C"].concat());
-                *created_c_ref.lock().unwrap() = Some(c.clone());
-                Ok(c)
-            }
+    /// Child of kind `K` that logs its construction and destruction.
+    struct Part<K>(Log, String, PhantomData<K>);
+    struct Channel;
+    struct Buffer;
+    struct Timer;
+    struct Link;
+    struct Peer(Log, &'static str);
+
+    impl<K> Part<K> {
+        fn new<S: Into<String>>(log: &Log, name: S) -> Part<K> {
+            let name = name.into();
+            log.lock().unwrap().push(format!("new {}", name));
+            Part(log.clone(), name, PhantomData)
+        }
+    }
+
+    impl<K> Drop for Part<K> {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().push(format!("drop {}", self.1));
+        }
+    }
+
+    fn session_deps() -> Deps {
+        let mut deps = Deps::new();
+        deps.attach(|_: &Deps, socket: &mut Socket| Ok(Part::<Channel>::new(&socket.0, "channel")));
+        deps.attach(|_: &Deps, channel: &mut Part<Channel>| Ok(Part::<Buffer>::new(&channel.0, "buffer")));
+        deps.attach(|_: &Deps, socket: &mut Socket| Ok(Part::<Timer>::new(&socket.0, "timer")));
+        deps.bridge(|_: &Deps, _: &mut Socket, peer: &mut Peer| {
+            Ok(Part::<Link>::new(&peer.0, format!("link {}", peer.1)))
+        });
+        deps.when_ready(|_: &Deps, socket: &mut Socket| {
+            socket.0.lock().unwrap().push("ready".into());
+            Ok(())
         });
+        deps
+    }
 
-        deps.create(A("Hello".into())).unwrap();
+    fn destroyed_in_reverse_order<F: FnOnce(Scope<Socket>)>(destroy: F) {
+        let deps = session_deps();
+        let log = Log::default();
+        let _peer = deps.create(Peer(log.clone(), "a")).unwrap();
 
-        assert_eq!("Hello+B+C",
-                   (*created_c_ref.lock().unwrap()).clone().unwrap().0);
+        let socket = deps.create(Socket(log.clone())).unwrap();
+        let created = mem::take(&mut *log.lock().unwrap());
+        destroy(socket);
+
+        assert_eq!(vec!["new channel", "new buffer", "new timer", "new link a", "ready"], created);
+        assert_eq!(vec!["drop link a", "drop timer", "drop buffer", "drop channel"], *log.lock().unwrap());
     }
 
     #[test]
-    fn creates_mutable_dependency() {
-        let mut deps = Deps::new();
+    fn drops_children_in_reverse_order_of_construction() {
+        destroyed_in_reverse_order(drop);
+    }
 
-        deps.attach(|_: &Deps, a: &mut A| {
-            *a = A("Hi!".into());
-            Ok(())
+    #[test]
+    fn explodes_children_in_reverse_order_of_construction() {
+        destroyed_in_reverse_order(|socket| {
+            socket.explode();
         });
+    }
 
-        let mut a = deps.create(A("Hello".into())).unwrap();
-        let al = a.lock().unwrap();
-
-        assert_eq!("Hi!", al.0);
+    #[test]
+    fn drops_bridged_children_in_reverse_order_of_construction() {
+        let deps = session_deps();
+        let log = Log::default();
+        let socket = deps.create(Socket(log.clone())).unwrap();
+        let peers: Vec<_> = ["a", "b", "c"].iter().map(|&name| deps.create(Peer(log.clone(), name)).unwrap()).collect();
+        log.lock().unwrap().clear();
+
+        drop(socket);
+
+        assert_eq!(vec!["drop link c", "drop link b", "drop link a", "drop timer", "drop buffer", "drop channel"],
+                   *log.lock().unwrap());
+        drop(peers);
+        assert_eq!(6, log.lock().unwrap().len());
     }
 
     #[test]
-    fn should_work_accross_threads() {
-        let mut deps = Deps::new();
+    fn rejects_value_before_creating_any_children() {
+        let deps = {
+            let mut deps = session_deps();
+            deps.before_create(|_: &Deps, socket: &mut Socket| socket.0.lock().unwrap().push("before".into()));
+            deps.validate(|_: &Deps, socket: &Socket| -> ::Result<()> {
+                socket.0.lock().unwrap().push("validated".into());
+                Err(Error::new("closed"))
+            });
+            deps
+        };
+        let log = Log::default();
+        let _peer = deps.create(Peer(log.clone(), "a")).unwrap();
 
-        deps.attach(|_: &Deps, _: &mut A| Ok(B("b".into())));
-        deps.attach(|_: &Deps, _: &mut B| Ok(C("c".into())));
+        let e = deps.try_create(Socket(log.clone())).unwrap_err();
 
-        let dep_refs = Arc::new(deps);
+        assert_eq!(Some(ErrorKind::Validate), e.error().kind());
+        assert_eq!(&[any::type_name::<Socket>()], e.error().path());
+        assert_eq!(format!("validate failed at {}: closed", any::type_name::<Socket>()), e.error().to_string());
+        assert_eq!(vec!["before", "validated"], *log.lock().unwrap());
 
-        let a = thread::spawn({
-            let a_deps = dep_refs.clone();
-            move || a_deps.create(A("a".into())).unwrap()
-        });
+        let _peer = deps.create(Peer(log.clone(), "b")).unwrap();
+        assert_eq!(vec!["before", "validated"], *log.lock().unwrap());
+    }
 
-        let b = thread::spawn({
-            let b_deps = dep_refs.clone();
-            move || b_deps.create(B("b".into())).unwrap()
+    #[test]
+    fn creates_value_that_passes_all_checks() {
+        let mut deps = Deps::new();
+
+        deps.validate(|_: &Deps, a: &A| -> ::Result<()> {
+            if a.0.is_empty() { Err(Error::new("empty")) } else { Ok(()) }
+        });
+        deps.validate(|_: &Deps, a: &A| -> ::Result<()> {
+            if a.0.len() > 3 { Err(Error::new("too long")) } else { Ok(()) }
         });
 
-        assert_eq!(b.join().unwrap().explode(), B("b".into()));
-        assert_eq!(a.join().unwrap().explode(), A("a".into()));
+        assert!(deps.create(A("abc".into())).is_ok());
+        assert_eq!("empty", deps.create(A("".into())).unwrap_err().cause().to_string());
+        assert_eq!("too long", deps.create(A("abcd".into())).unwrap_err().cause().to_string());
     }
 
     #[test]
-    fn can_create_bridge_dependency() {
+    fn decorates_value_in_order_of_registration_before_other_callbacks() {
         let mut deps = Deps::new();
 
-        let created_bridge = Arc::new(Mutex::new(None));
-        let created_bridge_clone = created_bridge.clone(); // so we can modify this from inside the closure
+        deps.attach(|_: &Deps, a: &mut A| Ok(B(a.0.clone())));
+        deps.before_create(|_: &Deps, a: &mut A| a.0.push('!'));
+        deps.decorate(|_: &Deps, a: A| Ok(A(format!("<{}>", a.0))));
+        deps.decorate(|_: &Deps, a: A| Ok(A(a.0 + "1")));
 
-        deps.bridge(|a: &mut A, b: &mut B| Ok(vec![a.0.clone(), b.0.clone()]));
+        let a = deps.create(A("a".into())).unwrap();
 
-        // Use this to copy created Vec<String> value from bridge to mutex protected clone
-        deps.when_ready(move |_: &Deps, parent: &mut Vec<String>| {
-            *created_bridge_clone.lock().unwrap() = Some(parent.clone());
-            Ok(())
-        });
+        assert_eq!("<a>1!", read(&a.child::<B>().unwrap(), |b| b.0.clone()));
+        assert_eq!(A("<a>1!".into()), a.explode());
+    }
 
-        // Bind to created A and modify the value from "Hello" to "Hi"
-        deps.attach(|_: &Deps, a: &mut A| {
-            *a = A("Hi".into());
-            Ok(5)
-        });
+    #[test]
+    fn gives_back_value_from_failed_decorator() {
+        let mut deps = Deps::new();
+        let attached = Arc::new(Mutex::new(0));
 
-        // Attach to any type Vec<String> and append "Nice" to last element
-        deps.attach(|_: &Deps, created_bridge_result: &mut Vec<String>| {
-            created_bridge_result.push("Nice".to_string());
-            Ok(())
+        {
+            let attached = attached.clone();
+            deps.attach(move |_: &Deps, _: &mut A| {
+                *attached.lock().unwrap() += 1;
+                Ok(())
+            });
+        }
+        deps.decorate(|_: &Deps, a: A| Ok(A(a.0.trim().into())));
+        deps.decorate(|_: &Deps, a: A| {
+            if a.0.is_empty() { Err(CreateError::new(a, Error::new("empty"))) } else { Ok(a) }
         });
 
-        // Create both instigators and result should appear
-        let mut a = deps.create(A("Hello".into())).unwrap();
-        let mut b = deps.create(B("World".into())).unwrap();
+        let e = deps.try_create(A(" ".into())).unwrap_err();
 
-        {
-            let al = a.lock().unwrap();
-            let bl = b.lock().unwrap();
+        assert_eq!(Some(ErrorKind::Decorate), e.error().kind());
+        assert_eq!(&[any::type_name::<A>()], e.error().path());
+        assert_eq!(&A("".into()), e.value());
+        assert_eq!(0, *attached.lock().unwrap());
+    }
 
-            assert_eq!("Hi", al.0);
-            assert_eq!("World", bl.0);
+    fn push(c: char) -> impl Fn(&Deps, &mut A) -> ::Result<()> + Send + Sync {
+        move |_: &Deps, a: &mut A| {
+            a.0.push(c);
+            Ok(())
         }
+    }
 
-        {
-            let val = created_bridge.lock()
-                .unwrap();
-            assert_eq!("HiWorldNice",
-                       val.as_ref().expect("expected bridge val to be created").concat());
-        }
+    #[test]
+    fn runs_ordered_registrations_in_resolved_order() {
+        let mut deps = Deps::new();
 
-        let mut c = deps.create(B("Rust".into())).unwrap();
+        deps.attach(push('3'));
+        deps.attach_ordered(Order::new().label("second").after("first"), push('2')).unwrap();
+        deps.attach_ordered(Order::new().label("first").priority(-1), push('1')).unwrap();
+        deps.attach_ordered(Order::new().priority(1), push('0')).unwrap();
+        deps.when_ready(push('b'));
+        deps.when_ready_ordered(Order::new().priority(1), push('a')).unwrap();
+        deps.collectable(|_: &Deps| 2);
+        deps.collectable_ordered(Order::new().before("missing").priority(1), |_: &Deps| 1).unwrap();
+
+        assert_eq!(A("a0312ab".into()), deps.create(A("a".into())).unwrap().explode());
+        assert_eq!(vec![1, 2], deps.collect::<i32>().unwrap().iter().cloned().collect::<Vec<_>>());
+    }
 
-        {
-            let cl = c.lock().unwrap();
+    #[test]
+    fn rejects_registration_with_contradictory_order() {
+        let mut deps = Deps::new();
 
-            assert_eq!("Rust", cl.0);
-        }
+        deps.when_ready_ordered(Order::new().label("pool").after("log"), |_: &Deps, _: &mut A| Ok(())).unwrap();
+        deps.when_ready_ordered(Order::new().label("log"), |_: &Deps, _: &mut A| Ok(())).unwrap();
+        let edges = deps.graph().edges().len();
 
-        {
-            let val = created_bridge.lock()
-                .unwrap();
-            assert_eq!("HiRustNice",
-                       val.as_ref().expect("expected bridge val to be created").concat());
-        }
+        let e = deps.when_ready_ordered(Order::new().label("metrics").after("pool").before("log"),
+                                        |_: &Deps, _: &mut A| panic!("expected to be rejected"))
+            .unwrap_err();
 
-        assert_eq!(c.explode(), B("Rust".into()));
-        assert_eq!(a.explode(), A("Hi".into()));
-        assert_eq!(b.explode(), B("World".into()));
+        assert_eq!(Some(ErrorKind::Order), e.kind());
+        assert_eq!(format!("order failed at {}: registrations pool, log, metrics have contradictory order",
+                           any::type_name::<A>()),
+                   e.to_string());
+        assert_eq!(edges, deps.graph().edges().len());
+        assert!(deps.create(A("a".into())).is_ok());
+    }
+
+    #[test]
+    fn orders_bridges_among_attached_constructors() {
+        let mut deps = Deps::new();
+
+        deps.attach_ordered(Order::new().label("last"), push('2')).unwrap();
+        deps.bridge_ordered(Order::new().after("first").before("last"),
+                            |_: &Deps, a: &mut A, b: &mut B| Ok(C([&a.0[..], &b.0[..]].concat())))
+            .unwrap();
+        deps.attach_ordered(Order::new().label("first"), push('1')).unwrap();
+
+        let _b = deps.create(B("b".into())).unwrap();
+        let a = deps.create(A("a".into())).unwrap();
+
+        assert_eq!("a1b", read(&a.child::<C>().unwrap(), |c| c.0.clone()));
+        assert_eq!("a12", read(&a, |a| a.0.clone()));
+    }
+
+    #[test]
+    fn rejects_bridge_with_contradictory_order_for_any_parent() {
+        let mut deps = Deps::new();
+        deps.attach_ordered(Order::new().label("link"), |_: &Deps, _: &mut B| Ok(())).unwrap();
+        let edges = deps.graph().edges().len();
+
+        let e = deps.bridge_ordered(Order::new().label("link"), |_: &Deps, _: &mut A, _: &mut B| Ok(C("".into())))
+            .unwrap_err();
+
+        assert_eq!(Some(ErrorKind::Order), e.kind());
+        assert_eq!(&[any::type_name::<B>()], e.path());
+        assert_eq!(edges, deps.graph().edges().len());
+        let a = deps.create(A("a".into())).unwrap();
+        let _b = deps.create(B("b".into())).unwrap();
+        assert!(a.child::<C>().is_none());
+    }
+
+    #[test]
+    fn can_send_deps_and_scopes_of_send_values_to_other_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Deps>();
+        assert_send_sync::<Scope<A>>();
+        assert_send_sync::<Scope<Arc<Mutex<A>>>>();
     }
 }
@@ -0,0 +1,409 @@
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use scope::Scope;
+
+/// Kind of registration whose callback failed, or other reason of failure.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// Constructor registered with `Deps::attach`.
+    Attach,
+    /// Constructor registered with one of `Deps::bridge` methods.
+    Bridge,
+    /// Action registered with `Deps::when_ready`.
+    WhenReady,
+    /// Check registered with `Deps::validate`.
+    Validate,
+    /// Decorator registered with `Deps::decorate`.
+    Decorate,
+    /// Types attached to each other form a cycle.
+    Cycle,
+    /// Order of a registration contradicts order of other registrations.
+    Order,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ErrorKind::Attach => "attach",
+            ErrorKind::Bridge => "bridge",
+            ErrorKind::WhenReady => "when_ready",
+            ErrorKind::Validate => "validate",
+            ErrorKind::Decorate => "decorate",
+            ErrorKind::Cycle => "cycle check",
+            ErrorKind::Order => "order",
+        })
+    }
+}
+
+/// Error returned from dependency construction.
+///
+/// Wraps the error returned by user callback, and records the chain of types,
+/// starting from the value passed to `Deps::create`, down to the failed one.
+///
+/// Any error that implements `std::error::Error + Send + Sync` can be converted into it
+/// with `?`, and messages can be turned into it with `Error::new`.
+pub struct Error {
+    kind: Option<ErrorKind>,
+    path: Vec<&'static str>,
+    cause: Box<dyn error::Error + Send + Sync>,
+    /// Errors that happened together with this one.
+    others: Vec<Error>,
+}
+
+impl Error {
+    pub fn new<E>(cause: E) -> Error
+        where E: Into<Box<dyn error::Error + Send + Sync>>
+    {
+        Error {
+            kind: None,
+            path: Vec::new(),
+            cause: cause.into(),
+            others: Vec::new(),
+        }
+    }
+
+    /// Fail with the first of `errors`, keeping the rest as its `others`, or succeed
+    /// if there are none.
+    pub(crate) fn collect(errors: Vec<Error>) -> ::Result<()> {
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            Some(mut first) => {
+                for mut error in errors {
+                    let others = mem::take(&mut error.others);
+                    first.others.push(error);
+                    first.others.extend(others);
+                }
+                Err(first)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Kind of registration whose callback failed, or `None` if the error was
+    /// not returned from a registered callback.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        self.kind
+    }
+
+    /// Type names from the created root down to the type whose construction failed.
+    pub fn path(&self) -> &[&'static str] {
+        &self.path
+    }
+
+    /// Error returned by user callback.
+    pub fn cause(&self) -> &(dyn error::Error + Send + Sync + 'static) {
+        &*self.cause
+    }
+
+    pub fn into_cause(self) -> Box<dyn error::Error + Send + Sync> {
+        self.cause
+    }
+
+    /// Errors of other bridges that failed together with this one, when bridges deferred
+    /// by a locked value are created on `MaybeMutexGuard::unlock`.
+    pub fn others(&self) -> &[Error] {
+        &self.others
+    }
+
+    /// Mark this error as returned from callback of specified registration kind,
+    /// unless it already came from deeper registration.
+    ///
+    /// The `child` is the name of the type the callback was constructing, if any.
+    pub(crate) fn returned_from(mut self, kind: ErrorKind, child: Option<&'static str>) -> Error {
+        if self.kind.is_none() {
+            self.kind = Some(kind);
+            self.path.extend(child);
+        }
+        self
+    }
+
+    /// Record that this error happened while creating dependencies of specified type.
+    pub(crate) fn within(mut self, parent: &'static str) -> Error {
+        self.path.insert(0, parent);
+        self
+    }
+}
+
+impl<E> From<E> for Error
+    where E: error::Error + Send + Sync + 'static
+{
+    fn from(cause: E) -> Error {
+        Error::new(cause)
+    }
+}
+
+/// Keeps kind and path of the error when it is converted into a boxed standard error,
+/// which `Error` itself can not implement without conflicting with its conversion from them.
+struct Reported(Error);
+
+impl fmt::Debug for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl error::Error for Reported {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.0.cause())
+    }
+}
+
+impl From<Error> for Box<dyn error::Error + Send + Sync> {
+    fn from(e: Error) -> Box<dyn error::Error + Send + Sync> {
+        Box::new(Reported(e))
+    }
+}
+
+impl From<Error> for Box<dyn error::Error> {
+    fn from(e: Error) -> Box<dyn error::Error> {
+        Box::new(Reported(e))
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("kind", &self.kind)
+            .field("path", &self.path)
+            .field("cause", &self.cause)
+            .field("others", &self.others)
+            .finish()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(kind) = self.kind {
+            write!(f, "{} failed", kind)?;
+        } else {
+            f.write_str("failed")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path.join(" -> "))?;
+        }
+        write!(f, ": {}", self.cause)?;
+        if !self.others.is_empty() {
+            write!(f, " (and {} more)", self.others.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Error of failed `Deps::try_create`, which gives back the value that was
+/// passed to it.
+///
+/// By the time this error is returned, all the children constructed for the value
+/// were already destroyed.
+///
+/// Decorators registered with `Deps::decorate` return it to give back the value they
+/// were passed, or the one they replaced it with.
+pub struct CreateError<T> {
+    value: T,
+    error: Error,
+}
+
+impl<T> CreateError<T> {
+    pub fn new(value: T, error: Error) -> CreateError<T> {
+        CreateError {
+            value,
+            error,
+        }
+    }
+
+    /// Value that failed to be created.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Error that caused the failure.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+
+    pub fn into_parts(self) -> (T, Error) {
+        (self.value, self.error)
+    }
+}
+
+impl<T> fmt::Debug for CreateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CreateError")
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for CreateError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to create value: {}", self.error)
+    }
+}
+
+impl<T> error::Error for CreateError<T> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.error.cause())
+    }
+}
+
+/// Reason why `Scope::try_explode` could not take the value out of a scope.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExplodeReason {
+    /// Shared value is still referenced outside of this scope and its bridges.
+    Shared,
+    /// Shared value mutex was poisoned by a panic.
+    Poisoned,
+}
+
+impl fmt::Display for ExplodeReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ExplodeReason::Shared => "value is still shared",
+            ExplodeReason::Poisoned => "value mutex is poisoned",
+        })
+    }
+}
+
+/// Error of failed `Scope::try_explode`, which gives back the scope.
+///
+/// The scope is `Scope<T>`, or `LocalScope<T>` for `LocalScope::try_explode`.
+pub struct ExplodeError<T, S = Scope<T>> {
+    scope: S,
+    reason: ExplodeReason,
+    value: PhantomData<T>,
+}
+
+impl<T, S> ExplodeError<T, S> {
+    pub fn new(scope: S, reason: ExplodeReason) -> ExplodeError<T, S> {
+        ExplodeError {
+            scope,
+            reason,
+            value: PhantomData,
+        }
+    }
+
+    /// Scope that failed to explode.
+    pub fn scope(&self) -> &S {
+        &self.scope
+    }
+
+    pub fn reason(&self) -> ExplodeReason {
+        self.reason
+    }
+
+    pub fn into_scope(self) -> S {
+        self.scope
+    }
+}
+
+impl<T, S> fmt::Debug for ExplodeError<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExplodeError")
+            .field("reason", &self.reason)
+            .finish()
+    }
+}
+
+impl<T, S> fmt::Display for ExplodeError<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to explode scope: {}", self.reason)
+    }
+}
+
+impl<T, S> error::Error for ExplodeError<T, S> {}
+
+#[cfg(test)]
+mod test {
+    use std::fmt;
+    use std::error;
+    use super::*;
+
+    #[derive(Debug)]
+    struct Failure;
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("failure")
+        }
+    }
+
+    impl error::Error for Failure {}
+
+    fn fail() -> ::Result<()> {
+        Err(Failure)?
+    }
+
+    #[test]
+    fn should_convert_any_error() {
+        let e = fail().unwrap_err();
+
+        assert_eq!(None, e.kind());
+        assert_eq!("failed: failure", e.to_string());
+    }
+
+    #[test]
+    fn should_keep_kind_of_deepest_registration() {
+        let e = Error::new("failure")
+            .returned_from(ErrorKind::WhenReady, None)
+            .within("Pool")
+            .returned_from(ErrorKind::Attach, Some("Pool"))
+            .within("Database")
+            .within("App");
+
+        assert_eq!(Some(ErrorKind::WhenReady), e.kind());
+        assert_eq!(&["App", "Database", "Pool"], e.path());
+        assert_eq!("when_ready failed at App -> Database -> Pool: failure", e.to_string());
+    }
+
+    #[test]
+    fn should_keep_kind_and_path_in_boxed_errors() {
+        fn boxed() -> Result<(), Box<dyn error::Error>> {
+            Err(Error::new(Failure).returned_from(ErrorKind::Attach, Some("Pool")).within("App"))?;
+            Ok(())
+        }
+        fn boxed_send_sync() -> Result<(), Box<dyn error::Error + Send + Sync>> {
+            Err(Error::new(Failure).returned_from(ErrorKind::Bridge, None))?;
+            Ok(())
+        }
+
+        let e = boxed().unwrap_err();
+        assert_eq!("attach failed at App -> Pool: failure", e.to_string());
+        assert_eq!("failure", e.source().unwrap().to_string());
+
+        assert_eq!("bridge failed: failure", boxed_send_sync().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn should_collect_errors_after_the_first_one() {
+        let nested = Error::collect(vec![Error::new("second"), Error::new("third")]).unwrap_err();
+
+        let e = Error::collect(vec![Error::new("first"), nested]).unwrap_err();
+
+        assert_eq!("failed: first (and 2 more)", e.to_string());
+        let others: Vec<_> = e.others().iter().map(ToString::to_string).collect();
+        assert_eq!(vec!["failed: second", "failed: third"], others);
+        assert!(Error::collect(Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn should_be_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Error>();
+    }
+}
@@ -0,0 +1,304 @@
+//! Flavors of dependencies, that can either be used from any thread or stay on one.
+//!
+//! Everything `Deps` keeps is type-erased into the associated types of its flavor, so
+//! `Deps` and `Scope` are `Send + Sync` only because what they keep is, and `LocalDeps`
+//! and `LocalScope` are neither.
+
+use std::any::Any;
+use std::fmt;
+use std::result;
+use std::sync::Arc;
+use constructed::{AnyInstance, Constructed};
+use inceptor::{Destructor, Group};
+use inspect::Child;
+use locks::{LocalShared, Share, Shared, SharedLock};
+use {CreateError, Result};
+use deps::Deps;
+
+/// Whether dependencies, their callbacks and the scopes they create can be used from
+/// other threads.
+///
+/// Implemented only by `Threaded`, for `Deps` and `Scope`, and by `Local`, for `LocalDeps`
+/// and `LocalScope`.
+pub trait Flavor: 'static + Clone + fmt::Debug {
+    /// Value shared with bridges.
+    type Shared<T>: Share<T>;
+
+    /// Type-erased value, such as a shared bridge parent or the key of its group.
+    type Any: ?Sized;
+
+    /// Child kept alive by a scope or a bridge.
+    type Child: ?Sized + Child<Self> + fmt::Debug;
+
+    /// Function that constructs all childs for a type and returns them wrapped in Any.
+    type Constructor: ?Sized + Fn(&Deps<Self>, &mut AnyInstance) -> Result<Constructed<Self>>;
+
+    /// Callback invoked after a value and all its dependencies were created.
+    type Action: ?Sized + Fn(&Deps<Self>, &mut AnyInstance) -> Result<()>;
+
+    /// Callback invoked with a value before any of its dependencies are created.
+    type CreatingHook: ?Sized + Fn(&Deps<Self>, &mut Box<dyn Any>);
+
+    /// Function that replaces a value before any of its dependencies are created, or gives
+    /// back a value if it fails.
+    type Decorator: ?Sized + Fn(&Deps<Self>, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>>;
+
+    /// Check that can reject a value before any of its dependencies are created.
+    type Validator: ?Sized + Fn(&Deps<Self>, &dyn Any) -> Result<()>;
+
+    /// Callback registered for a type with `Deps::when_dropping` or `Deps::when_dropped`.
+    type DropHook: ?Sized + Fn(&mut dyn Any);
+
+    /// Type-erased bridge constructor.
+    ///
+    /// Receives `Deps` that are creating the parent, and one shared parent per bridged
+    /// position, each of them a `Shared<P>` of that position's type wrapped in `Any`.
+    type BridgeConstructor: ?Sized + Fn(&Deps<Self>, &[&dyn Any]) -> Result<Option<Box<dyn Any>>>;
+
+    /// Type-erased bridge teardown.
+    ///
+    /// Receives the same parents as `BridgeConstructor`, together with the child
+    /// owned by inceptor.
+    type BridgeTeardown: ?Sized + Fn(&[&dyn Any], &mut Self::Child);
+
+    /// Type-erased bridge grouping.
+    ///
+    /// Receives parent position and the parent, and returns its group. Only parents
+    /// in the same group are bridged.
+    type BridgeGroup: ?Sized + Fn(usize, &dyn Any) -> Option<Group<Self>>;
+
+    /// Type-erased bridge filter.
+    ///
+    /// Receives the same parents as `BridgeConstructor`, and returns whether the constructor
+    /// should be invoked for them.
+    type BridgeFilter: ?Sized + Fn(&[&dyn Any]) -> bool;
+
+    /// Share value with bridges in specified lock, if this flavor has locks.
+    fn share<T>(value: T, lock: SharedLock) -> Self::Shared<T>;
+
+    fn any(value: &Self::Any) -> &dyn Any;
+
+    /// Keep destructor of bridged parent as a child of its scope.
+    fn destructor(destructor: Destructor<Self>) -> Box<Self::Child>;
+}
+
+/// Flavor of `Deps` and `Scope`, that keep only values and callbacks that are `Send + Sync`.
+#[derive(Clone, Copy, Debug)]
+pub enum Threaded {}
+
+/// Flavor of `LocalDeps` and `LocalScope`, that keep any values and callbacks, and stay
+/// on the thread they were created on.
+#[derive(Clone, Copy, Debug)]
+pub enum Local {}
+
+impl Flavor for Threaded {
+    type Shared<T> = Shared<T>;
+    type Any = dyn Any + Send + Sync;
+    type Child = dyn Child<Threaded> + Send + Sync;
+    type Constructor = dyn Fn(&Deps, &mut AnyInstance) -> Result<Constructed<Threaded>> + Send + Sync;
+    type Action = dyn Fn(&Deps, &mut AnyInstance) -> Result<()> + Send + Sync;
+    type CreatingHook = dyn Fn(&Deps, &mut Box<dyn Any>) + Send + Sync;
+    type Decorator = dyn Fn(&Deps, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>> + Send + Sync;
+    type Validator = dyn Fn(&Deps, &dyn Any) -> Result<()> + Send + Sync;
+    type DropHook = dyn Fn(&mut dyn Any) + Send + Sync;
+    type BridgeConstructor = dyn Fn(&Deps, &[&dyn Any]) -> Result<Option<Box<dyn Any>>> + Send + Sync;
+    type BridgeTeardown = dyn Fn(&[&dyn Any], &mut Self::Child) + Send + Sync;
+    type BridgeGroup = dyn Fn(usize, &dyn Any) -> Option<Group<Threaded>> + Send + Sync;
+    type BridgeFilter = dyn Fn(&[&dyn Any]) -> bool + Send + Sync;
+
+    fn share<T>(value: T, lock: SharedLock) -> Shared<T> {
+        Shared::new(value, lock)
+    }
+
+    fn any(value: &Self::Any) -> &dyn Any {
+        value
+    }
+
+    fn destructor(destructor: Destructor<Threaded>) -> Box<Self::Child> {
+        Box::new(destructor)
+    }
+}
+
+impl Flavor for Local {
+    type Shared<T> = LocalShared<T>;
+    type Any = dyn Any;
+    type Child = dyn Child<Local>;
+    type Constructor = dyn Fn(&Deps<Local>, &mut AnyInstance) -> Result<Constructed<Local>>;
+    type Action = dyn Fn(&Deps<Local>, &mut AnyInstance) -> Result<()>;
+    type CreatingHook = dyn Fn(&Deps<Local>, &mut Box<dyn Any>);
+    type Decorator = dyn Fn(&Deps<Local>, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>>;
+    type Validator = dyn Fn(&Deps<Local>, &dyn Any) -> Result<()>;
+    type DropHook = dyn Fn(&mut dyn Any);
+    type BridgeConstructor = dyn Fn(&Deps<Local>, &[&dyn Any]) -> Result<Option<Box<dyn Any>>>;
+    type BridgeTeardown = dyn Fn(&[&dyn Any], &mut Self::Child);
+    type BridgeGroup = dyn Fn(usize, &dyn Any) -> Option<Group<Local>>;
+    type BridgeFilter = dyn Fn(&[&dyn Any]) -> bool;
+
+    /// Values are shared in `RefCell` on a single thread, so there is no lock to choose.
+    fn share<T>(value: T, _lock: SharedLock) -> LocalShared<T> {
+        LocalShared::new(value)
+    }
+
+    fn any(value: &Self::Any) -> &dyn Any {
+        value
+    }
+
+    fn destructor(destructor: Destructor<Local>) -> Box<Self::Child> {
+        Box::new(destructor)
+    }
+}
+
+/// Flavor that can keep values of type `T`, and callbacks that own them.
+///
+/// `Threaded` keeps only values that are `Send + Sync`, `Local` keeps any. Callbacks are
+/// created from the `value` they own and a function `call` that receives it, so that
+/// they are `Send + Sync` whenever their value is.
+pub trait Erase<T>: Flavor {
+    fn erase(value: T) -> Box<Self::Any>;
+
+    fn child(scope: T) -> Box<Self::Child> where T: Child<Self>;
+
+    fn constructor(value: T,
+                   call: fn(&T, &Deps<Self>, &mut AnyInstance) -> Result<Constructed<Self>>)
+                   -> Arc<Self::Constructor>;
+
+    fn action(value: T, call: fn(&T, &Deps<Self>, &mut AnyInstance) -> Result<()>) -> Arc<Self::Action>;
+
+    fn creating_hook(value: T, call: fn(&T, &Deps<Self>, &mut Box<dyn Any>)) -> Arc<Self::CreatingHook>;
+
+    #[allow(clippy::type_complexity)]
+    fn decorator(value: T,
+                 call: fn(&T, &Deps<Self>, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>>)
+                 -> Arc<Self::Decorator>;
+
+    fn validator(value: T, call: fn(&T, &Deps<Self>, &dyn Any) -> Result<()>) -> Arc<Self::Validator>;
+
+    fn drop_hook(value: T, call: fn(&T, &mut dyn Any)) -> Arc<Self::DropHook>;
+
+    #[allow(clippy::type_complexity)]
+    fn bridge_constructor(value: T,
+                          call: fn(&T, &Deps<Self>, &[&dyn Any]) -> Result<Option<Box<dyn Any>>>)
+                          -> Arc<Self::BridgeConstructor>;
+
+    fn bridge_teardown(value: T, call: fn(&T, &[&dyn Any], &mut Self::Child)) -> Arc<Self::BridgeTeardown>;
+
+    fn bridge_group(value: T, call: fn(&T, usize, &dyn Any) -> Option<Group<Self>>) -> Arc<Self::BridgeGroup>;
+
+    fn bridge_filter(value: T, call: fn(&T, &[&dyn Any]) -> bool) -> Arc<Self::BridgeFilter>;
+}
+
+impl<T: Any + Send + Sync> Erase<T> for Threaded {
+    fn erase(value: T) -> Box<Self::Any> {
+        Box::new(value)
+    }
+
+    fn child(scope: T) -> Box<Self::Child>
+        where T: Child<Threaded>
+    {
+        Box::new(scope)
+    }
+
+    fn constructor(value: T,
+                   call: fn(&T, &Deps, &mut AnyInstance) -> Result<Constructed<Threaded>>)
+                   -> Arc<Self::Constructor> {
+        Arc::new(move |deps: &Deps, parent: &mut AnyInstance| call(&value, deps, parent))
+    }
+
+    fn action(value: T, call: fn(&T, &Deps, &mut AnyInstance) -> Result<()>) -> Arc<Self::Action> {
+        Arc::new(move |deps: &Deps, parent: &mut AnyInstance| call(&value, deps, parent))
+    }
+
+    fn creating_hook(value: T, call: fn(&T, &Deps, &mut Box<dyn Any>)) -> Arc<Self::CreatingHook> {
+        Arc::new(move |deps: &Deps, parent: &mut Box<dyn Any>| call(&value, deps, parent))
+    }
+
+    fn decorator(value: T,
+                 call: fn(&T, &Deps, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>>)
+                 -> Arc<Self::Decorator> {
+        Arc::new(move |deps: &Deps, parent: Box<dyn Any>| call(&value, deps, parent))
+    }
+
+    fn validator(value: T, call: fn(&T, &Deps, &dyn Any) -> Result<()>) -> Arc<Self::Validator> {
+        Arc::new(move |deps: &Deps, parent: &dyn Any| call(&value, deps, parent))
+    }
+
+    fn drop_hook(value: T, call: fn(&T, &mut dyn Any)) -> Arc<Self::DropHook> {
+        Arc::new(move |parent: &mut dyn Any| call(&value, parent))
+    }
+
+    fn bridge_constructor(value: T,
+                          call: fn(&T, &Deps, &[&dyn Any]) -> Result<Option<Box<dyn Any>>>)
+                          -> Arc<Self::BridgeConstructor> {
+        Arc::new(move |deps: &Deps, parents: &[&dyn Any]| call(&value, deps, parents))
+    }
+
+    fn bridge_teardown(value: T, call: fn(&T, &[&dyn Any], &mut Self::Child)) -> Arc<Self::BridgeTeardown> {
+        Arc::new(move |parents: &[&dyn Any], child: &mut Self::Child| call(&value, parents, child))
+    }
+
+    fn bridge_group(value: T, call: fn(&T, usize, &dyn Any) -> Option<Group<Threaded>>) -> Arc<Self::BridgeGroup> {
+        Arc::new(move |index: usize, parent: &dyn Any| call(&value, index, parent))
+    }
+
+    fn bridge_filter(value: T, call: fn(&T, &[&dyn Any]) -> bool) -> Arc<Self::BridgeFilter> {
+        Arc::new(move |parents: &[&dyn Any]| call(&value, parents))
+    }
+}
+
+impl<T: Any> Erase<T> for Local {
+    fn erase(value: T) -> Box<Self::Any> {
+        Box::new(value)
+    }
+
+    fn child(scope: T) -> Box<Self::Child>
+        where T: Child<Local>
+    {
+        Box::new(scope)
+    }
+
+    fn constructor(value: T,
+                   call: fn(&T, &Deps<Local>, &mut AnyInstance) -> Result<Constructed<Local>>)
+                   -> Arc<Self::Constructor> {
+        Arc::new(move |deps: &Deps<Local>, parent: &mut AnyInstance| call(&value, deps, parent))
+    }
+
+    fn action(value: T, call: fn(&T, &Deps<Local>, &mut AnyInstance) -> Result<()>) -> Arc<Self::Action> {
+        Arc::new(move |deps: &Deps<Local>, parent: &mut AnyInstance| call(&value, deps, parent))
+    }
+
+    fn creating_hook(value: T, call: fn(&T, &Deps<Local>, &mut Box<dyn Any>)) -> Arc<Self::CreatingHook> {
+        Arc::new(move |deps: &Deps<Local>, parent: &mut Box<dyn Any>| call(&value, deps, parent))
+    }
+
+    fn decorator(value: T,
+                 call: fn(&T, &Deps<Local>, Box<dyn Any>) -> result::Result<Box<dyn Any>, CreateError<Box<dyn Any>>>)
+                 -> Arc<Self::Decorator> {
+        Arc::new(move |deps: &Deps<Local>, parent: Box<dyn Any>| call(&value, deps, parent))
+    }
+
+    fn validator(value: T, call: fn(&T, &Deps<Local>, &dyn Any) -> Result<()>) -> Arc<Self::Validator> {
+        Arc::new(move |deps: &Deps<Local>, parent: &dyn Any| call(&value, deps, parent))
+    }
+
+    fn drop_hook(value: T, call: fn(&T, &mut dyn Any)) -> Arc<Self::DropHook> {
+        Arc::new(move |parent: &mut dyn Any| call(&value, parent))
+    }
+
+    fn bridge_constructor(value: T,
+                          call: fn(&T, &Deps<Local>, &[&dyn Any]) -> Result<Option<Box<dyn Any>>>)
+                          -> Arc<Self::BridgeConstructor> {
+        Arc::new(move |deps: &Deps<Local>, parents: &[&dyn Any]| call(&value, deps, parents))
+    }
+
+    fn bridge_teardown(value: T, call: fn(&T, &[&dyn Any], &mut Self::Child)) -> Arc<Self::BridgeTeardown> {
+        Arc::new(move |parents: &[&dyn Any], child: &mut Self::Child| call(&value, parents, child))
+    }
+
+    fn bridge_group(value: T, call: fn(&T, usize, &dyn Any) -> Option<Group<Local>>) -> Arc<Self::BridgeGroup> {
+        Arc::new(move |index: usize, parent: &dyn Any| call(&value, index, parent))
+    }
+
+    fn bridge_filter(value: T, call: fn(&T, &[&dyn Any]) -> bool) -> Arc<Self::BridgeFilter> {
+        Arc::new(move |parents: &[&dyn Any]| call(&value, parents))
+    }
+}
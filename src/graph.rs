@@ -0,0 +1,284 @@
+//! Description of registered dependencies.
+
+use std::any::{self, Any, TypeId};
+use std::io;
+
+/// Type that takes part in registration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Node {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl Node {
+    pub fn of<T: Any>() -> Node {
+        Node {
+            id: TypeId::of::<T>(),
+            name: any::type_name::<T>(),
+        }
+    }
+
+    pub fn id(&self) -> TypeId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Kind of registration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EdgeKind {
+    /// Registered with `Deps::attach`.
+    Attach,
+    /// Registered with one of `Deps::bridge` methods.
+    Bridge,
+    /// Registered with `Deps::collectable`.
+    Collectable,
+    /// Registered with `Deps::when_ready`.
+    WhenReady,
+    /// Registered with `Deps::before_create`.
+    BeforeCreate,
+    /// Registered with `Deps::when_dropping`.
+    WhenDropping,
+    /// Registered with `Deps::when_dropped`.
+    WhenDropped,
+    /// Registered with `Deps::validate`.
+    Validate,
+    /// Registered with `Deps::decorate`.
+    Decorate,
+}
+
+impl EdgeKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            EdgeKind::Attach => "attach",
+            EdgeKind::Bridge => "bridge",
+            EdgeKind::Collectable => "collectable",
+            EdgeKind::WhenReady => "when_ready",
+            EdgeKind::BeforeCreate => "before_create",
+            EdgeKind::WhenDropping => "when_dropping",
+            EdgeKind::WhenDropped => "when_dropped",
+            EdgeKind::Validate => "validate",
+            EdgeKind::Decorate => "decorate",
+        }
+    }
+}
+
+/// Single registration: the parent types it is invoked for, and the child type it creates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Edge {
+    kind: EdgeKind,
+    parents: Vec<Node>,
+    child: Option<Node>,
+}
+
+impl Edge {
+    pub fn new(kind: EdgeKind, parents: Vec<Node>, child: Option<Node>) -> Edge {
+        Edge {
+            kind,
+            parents,
+            child,
+        }
+    }
+
+    pub fn kind(&self) -> EdgeKind {
+        self.kind
+    }
+
+    pub fn parents(&self) -> &[Node] {
+        &self.parents
+    }
+
+    /// Created child type, or `None` if registration does not create one.
+    pub fn child(&self) -> Option<Node> {
+        self.child
+    }
+}
+
+/// All registrations of `Deps`, in order of registration.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph { edges: Vec::new() }
+    }
+
+    pub fn push(&mut self, edge: Edge) {
+        self.edges.push(edge);
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Write graph in Graphviz DOT format.
+    ///
+    /// Registrations that have a single parent and a child are written as labeled edges,
+    /// others get an intermediate node labeled with registration kind.
+    pub fn write_dot<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "digraph deps {{")?;
+
+        let mut nodes: Vec<Node> = Vec::new();
+        for edge in &self.edges {
+            for node in edge.parents.iter().chain(edge.child.iter()) {
+                if !nodes.contains(node) {
+                    nodes.push(*node);
+                }
+            }
+        }
+        for node in &nodes {
+            writeln!(out, "    {} [label={}];", node_id(&nodes, node), dot_string(node.name))?;
+        }
+
+        for (i, edge) in self.edges.iter().enumerate() {
+            match (&edge.parents[..], edge.child) {
+                ([parent], Some(ref child)) => {
+                    writeln!(out,
+                             "    {} -> {} [label={}];",
+                             node_id(&nodes, parent),
+                             node_id(&nodes, child),
+                             dot_string(edge.kind.as_str()))?;
+                }
+                (parents, child) => {
+                    writeln!(out,
+                             "    e{} [shape=box, style=dashed, label={}];",
+                             i,
+                             dot_string(edge.kind.as_str()))?;
+                    for parent in parents {
+                        writeln!(out, "    {} -> e{};", node_id(&nodes, parent), i)?;
+                    }
+                    if let Some(ref child) = child {
+                        writeln!(out, "    e{} -> {};", i, node_id(&nodes, child))?;
+                    }
+                }
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// Write graph as JSON object with the list of `edges`.
+    ///
+    /// Every edge has `kind`, the list of `parents` and the `child` type names,
+    /// where `child` is `null` if registration does not create one.
+    pub fn write_json<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{{\"edges\":[")?;
+        for (i, edge) in self.edges.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{{\"kind\":{},\"parents\":[", json_string(edge.kind.as_str()))?;
+            for (j, parent) in edge.parents.iter().enumerate() {
+                if j > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{}", json_string(parent.name))?;
+            }
+            write!(out, "],\"child\":")?;
+            match edge.child {
+                Some(ref child) => write!(out, "{}", json_string(child.name))?,
+                None => write!(out, "null")?,
+            }
+            write!(out, "}}")?;
+        }
+        write!(out, "]}}")
+    }
+}
+
+fn node_id(nodes: &[Node], node: &Node) -> String {
+    format!("n{}",
+            nodes.iter().position(|n| n == node).expect("expected node to be collected"))
+}
+
+fn dot_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('"');
+    result
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph() -> Graph {
+        let mut graph = Graph::new();
+        graph.push(Edge::new(EdgeKind::Attach, vec![Node::of::<u8>()], Some(Node::of::<u16>())));
+        graph.push(Edge::new(EdgeKind::Bridge,
+                             vec![Node::of::<u8>(), Node::of::<String>()],
+                             Some(Node::of::<Vec<u8>>())));
+        graph.push(Edge::new(EdgeKind::WhenReady, vec![Node::of::<u16>()], None));
+        graph
+    }
+
+    #[test]
+    fn should_write_dot() {
+        let mut out = Vec::new();
+        graph().write_dot(&mut out).unwrap();
+
+        assert_eq!("digraph deps {
+    n0 [label=\"u8\"];
+    n1 [label=\"u16\"];
+    n2 [label=\"alloc::string::String\"];
+    n3 [label=\"alloc::vec::Vec<u8>\"];
+    n0 -> n1 [label=\"attach\"];
+    e1 [shape=box, style=dashed, label=\"bridge\"];
+    n0 -> e1;
+    n2 -> e1;
+    e1 -> n3;
+    e2 [shape=box, style=dashed, label=\"when_ready\"];
+    n1 -> e2;
+}
+",
+                   String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn should_write_json() {
+        let mut out = Vec::new();
+        graph().write_json(&mut out).unwrap();
+
+        assert_eq!("{\"edges\":[\
+                    {\"kind\":\"attach\",\"parents\":[\"u8\"],\"child\":\"u16\"},\
+                    {\"kind\":\"bridge\",\"parents\":[\"u8\",\"alloc::string::String\"],\
+                    \"child\":\"alloc::vec::Vec<u8>\"},\
+                    {\"kind\":\"when_ready\",\"parents\":[\"u16\"],\"child\":null}\
+                    ]}",
+                   String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn should_escape_strings() {
+        assert_eq!("\"a\\\"b\\\\c\"", dot_string("a\"b\\c"));
+        assert_eq!("\"a\\\"b\\\\c\\n\\u0001\"", json_string("a\"b\\c\n\u{1}"));
+    }
+}
@@ -1,125 +1,738 @@
 //! It incepts.
+//!
+//! Inceptor only keeps track of bridged parents and children under its lock. User callbacks
+//! are invoked by `incept` and `Destructor` after the lock is released, so they can create
+//! and drop other bridged values.
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::hash::{Hash, Hasher};
 use std::mem;
-use Result;
+use std::ops::{Deref, DerefMut};
+use flavor::{Erase, Flavor};
+use graph::Edge;
+use inspect::{BridgeInfo, Child, Visitor};
+use {Error, Result};
+use deps::Deps;
 
-pub struct Inceptor<T1, T2> {
-    d1: Vec<Option<Arc<Mutex<T1>>>>,
-    d2: Vec<Option<Arc<Mutex<T2>>>>,
-    used_size1: usize,
-    used_size2: usize,
-    constructor: Arc<Fn(&mut T1, &mut T2) -> Result<Option<Box<Any>>> + Send + Sync>,
+/// Type-erased creation of dependencies for value returned by bridge constructor.
+pub type AnyBridgeChild<F> = fn(&Deps<F>, Box<dyn Any>) -> Result<Box<<F as Flavor>::Child>>;
+
+/// Group of bridged parent, identified by a key value.
+pub struct Group<F: Flavor> {
+    hash: u64,
+    key: Box<F::Any>,
+    eq: fn(&dyn Any, &dyn Any) -> bool,
 }
 
-impl<T1: Any, T2: Any> Inceptor<T1, T2> {
-    pub fn new<F: 'static>(constructor: F) -> Inceptor<T1, T2>
-        where F: Fn(&mut T1, &mut T2) -> Result<Option<Box<Any>>> + Send + Sync
+impl<F: Flavor> Group<F> {
+    pub fn new<K>(key: K) -> Group<F>
+        where K: 'static + Hash + Eq,
+              F: Erase<K>
     {
-        Inceptor {
-            d1: Vec::new(),
-            d2: Vec::new(),
-            used_size1: 0,
-            used_size2: 0,
-            constructor: Arc::new(constructor),
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Group {
+            hash: hasher.finish(),
+            key: F::erase(key),
+            eq: key_eq::<K>,
         }
     }
+}
 
-    pub fn new_with_ignored_return_val<C, F>(constructor: F) -> Inceptor<T1, T2>
-        where C: 'static + Any,
-              F: for<'r> Fn(&mut T1, &mut T2) -> Result<C> + 'static + Send + Sync
-    {
-        Self::new(move |p1: &mut T1, p2: &mut T2| -> Result<Option<Box<Any>>> {
-            try!(constructor(p1, p2));
-            Ok(None)
-        })
+impl<F: Flavor> PartialEq for Group<F> {
+    fn eq(&self, other: &Group<F>) -> bool {
+        self.hash == other.hash && (self.eq)(F::any(&*self.key), F::any(&*other.key))
     }
+}
 
-    pub fn new_with_return_val<C, F>(constructor: F) -> Inceptor<T1, T2>
-        where C: 'static + Any,
-              F: for<'r> Fn(&mut T1, &mut T2) -> Result<C> + 'static + Send + Sync
-    {
-        Self::new(move |p1: &mut T1, p2: &mut T2| -> Result<Option<Box<Any>>> {
-            Ok(Some(Box::new(try!(constructor(p1, p2)))))
+fn key_eq<K: 'static + Eq>(a: &dyn Any, b: &dyn Any) -> bool {
+    a.downcast_ref::<K>() == b.downcast_ref::<K>()
+}
+
+/// Shared parent registered in inceptor, `F::Shared<P>` erased into `F::Any`.
+pub type AnyParent<F> = Arc<<F as Flavor>::Any>;
+
+/// Child owned by inceptor, locked separately so that it can be visited without
+/// locking the inceptor.
+pub type SharedChild<F> = Arc<ChildCell<F>>;
+
+/// Bridged child, or what became of it.
+enum ChildState<F: Flavor> {
+    Unlocked(Box<F::Child>),
+    /// Taken out by `ChildLock` or by the orphan that is finishing it.
+    Locked,
+    /// Torn down and dropped by its orphan.
+    Dropped,
+}
+
+/// Cell of bridged child, that the child is taken out of while it is locked.
+pub struct ChildCell<F: Flavor> {
+    state: Mutex<ChildState<F>>,
+    unlocked: Condvar,
+}
+
+impl<F: Flavor> ChildCell<F> {
+    fn new(child: Box<F::Child>) -> SharedChild<F> {
+        Arc::new(ChildCell {
+            state: Mutex::new(ChildState::Unlocked(child)),
+            unlocked: Condvar::new(),
         })
     }
 
-    fn invoke(&mut self, i1: usize, i2: usize) -> Result<Option<Box<Any>>> {
-        let val1: &mut Arc<Mutex<T1>> = match *self.d1
-            .get_mut(i1)
-            .expect("expected to find i1") {
-            Some(ref mut val) => val,
-            None => unreachable!("expected i1 to exist at slot"),
-        };
-        let val2 = match *self.d2
-            .get_mut(i2)
-            .expect("expected to find i2") {
-            Some(ref mut val) => val,
-            None => unreachable!("expected i2 to exist at slot"),
+    /// Take the child out, waiting while another thread keeps it locked, or return `None`
+    /// if it was dropped.
+    fn take(&self) -> Option<Box<F::Child>> {
+        let mut state = self.state.lock().expect("failed to lock bridged child");
+        loop {
+            match mem::replace(&mut *state, ChildState::Locked) {
+                ChildState::Unlocked(child) => return Some(child),
+                ChildState::Locked => {
+                    state = self.unlocked.wait(state).expect("failed to lock bridged child");
+                }
+                ChildState::Dropped => {
+                    *state = ChildState::Dropped;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Put back the child taken out by `take`, or mark it as dropped if there is none.
+    fn put(&self, child: Option<Box<F::Child>>) {
+        *self.state.lock().expect("failed to lock bridged child") = match child {
+            Some(child) => ChildState::Unlocked(child),
+            None => ChildState::Dropped,
         };
-        let mut locked1 = val1.lock().expect("expected to lock val1");
-        let mut locked2 = val2.lock().expect("expected to lock val2");
-        (self.constructor)(&mut locked1, &mut locked2)
-    }
-
-    pub fn incept_1(&mut self, parent: Arc<Mutex<T1>>) -> Result<(usize, Vec<Box<Any>>)> {
-        let id = insert_into_vec(&mut self.d1, parent, &mut self.used_size1);
-        let mut results = Vec::new();
-        for i2 in 0..self.d2.len() {
-            if !self.d2[i2].is_none() {
-                match try!(self.invoke(id, i2)) {
-                    Some(res) => results.push(res),
-                    None => (),
+        self.unlocked.notify_all();
+    }
+}
+
+/// Parents that should be bridged, and the key of their serials to `adopt` the child with.
+///
+/// Combination keeps its parents shared until it is bridged, so a parent value can not be
+/// taken out of its scope while another thread is bridging it.
+pub struct Combination<F: Flavor> {
+    key: Vec<usize>,
+    parents: Vec<AnyParent<F>>,
+    /// Addresses of parent mutexes.
+    addresses: Vec<usize>,
+}
+
+impl<F: Flavor> Combination<F> {
+    fn parents(&self) -> Vec<&dyn Any> {
+        self.parents.iter().map(|parent| F::any(parent)).collect()
+    }
+}
+
+/// Child given away by `Inceptor::destroy`, with the parents it was created for.
+struct Orphan<F: Flavor> {
+    teardown: Option<Arc<F::BridgeTeardown>>,
+    parents: Vec<AnyParent<F>>,
+    child: SharedChild<F>,
+}
+
+impl<F: Flavor> Orphan<F> {
+    /// Invoke teardown for the child and drop it.
+    fn finish(self) {
+        if let Some(mut child) = self.child.take() {
+            if let Some(ref teardown) = self.teardown {
+                let parents: Vec<&dyn Any> = self.parents.iter().map(|p| F::any(p)).collect();
+                teardown(&parents, &mut *child);
+            }
+            self.child.put(None);
+        }
+    }
+}
+
+/// Children given away by `Inceptor::destroy`, that should be torn down and dropped
+/// after the inceptor is unlocked.
+pub struct Destroyed<F: Flavor> {
+    orphans: Vec<Orphan<F>>,
+}
+
+impl<F: Flavor> Destroyed<F> {
+    /// Invoke teardown for every child, and drop them in reverse order of construction.
+    ///
+    /// Children that this thread keeps locked with `ChildLock` are finished only when
+    /// they are unlocked.
+    pub fn finish(self) {
+        for orphan in self.orphans.into_iter().rev() {
+            if is_child_locked(&orphan.child) {
+                let address = child_address(&orphan.child);
+                UNLOCKED_ORPHANS.with(|orphans| orphans.borrow_mut().push((address, Box::new(move || orphan.finish()))));
+            } else {
+                orphan.finish();
+            }
+        }
+    }
+}
+
+/// Callbacks of inceptor, cloned out of it so that they can be invoked while it is unlocked.
+struct Callbacks<F: Flavor> {
+    constructor: Arc<F::BridgeConstructor>,
+    child: Option<AnyBridgeChild<F>>,
+    teardown: Option<Arc<F::BridgeTeardown>>,
+    group: Option<Arc<F::BridgeGroup>>,
+    filter: Option<Arc<F::BridgeFilter>>,
+}
+
+impl<F: Flavor> Clone for Callbacks<F> {
+    fn clone(&self) -> Self {
+        Callbacks {
+            constructor: self.constructor.clone(),
+            child: self.child,
+            teardown: self.teardown.clone(),
+            group: self.group.clone(),
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+/// Parent instance registered in inceptor.
+struct Slot<F: Flavor> {
+    /// Unique number of this parent, never reused by other parents.
+    serial: usize,
+    /// Group this parent can be bridged in.
+    group: Option<Group<F>>,
+    /// Address of parent mutex.
+    address: usize,
+    parent: AnyParent<F>,
+}
+
+pub struct Inceptor<F: Flavor> {
+    /// Bridge registration this inceptor was created for.
+    edge: Edge,
+    /// Parent slots, one list for every bridged parent position, or a single list for peers.
+    parents: Vec<Vec<Option<Slot<F>>>>,
+    /// Whether all positions are taken by peers from a single list.
+    peers: bool,
+    /// Number of used slots for every list.
+    used_sizes: Vec<usize>,
+    /// Ids of grouped slots by group hash, for every list.
+    grouped: Vec<HashMap<u64, Vec<usize>>>,
+    /// Children owned by inceptor, together with serials of parents they were created for.
+    children: Vec<(Vec<usize>, SharedChild<F>)>,
+    next_serial: usize,
+    callbacks: Callbacks<F>,
+}
+
+impl<F: Flavor> Inceptor<F> {
+    pub fn new(edge: Edge, constructor: Arc<F::BridgeConstructor>) -> Inceptor<F> {
+        let arity = edge.parents().len();
+        Inceptor {
+            edge,
+            parents: (0..arity).map(|_| Vec::new()).collect(),
+            peers: false,
+            used_sizes: vec![0; arity],
+            grouped: (0..arity).map(|_| HashMap::new()).collect(),
+            children: Vec::new(),
+            next_serial: 0,
+            callbacks: Callbacks {
+                constructor,
+                child: None,
+                teardown: None,
+                group: None,
+                filter: None,
+            },
+        }
+    }
+
+    /// Set creation of dependencies for values returned by constructor.
+    pub fn with_child(mut self, child: AnyBridgeChild<F>) -> Inceptor<F> {
+        self.callbacks.child = Some(child);
+        self
+    }
+
+    /// Set teardown that is invoked for every owned child when any of its parents is destroyed.
+    pub fn with_teardown(mut self, teardown: Arc<F::BridgeTeardown>) -> Inceptor<F> {
+        self.callbacks.teardown = Some(teardown);
+        self
+    }
+
+    /// Take parents for both positions from a single list, so that every unordered pair
+    /// of distinct parents is bridged once.
+    pub fn with_peers(mut self) -> Inceptor<F> {
+        assert_eq!(2, self.parents.len(), "expected peers to be bridged in pairs");
+        self.parents.truncate(1);
+        self.used_sizes.truncate(1);
+        self.grouped.truncate(1);
+        self.peers = true;
+        self
+    }
+
+    /// Registration of the bridge.
+    pub fn edge(&self) -> &Edge {
+        &self.edge
+    }
+
+    pub fn is_peers(&self) -> bool {
+        self.peers
+    }
+
+    /// Set grouping, so that only parents in the same group are bridged.
+    pub fn with_group(mut self, group: Arc<F::BridgeGroup>) -> Inceptor<F> {
+        self.callbacks.group = Some(group);
+        self
+    }
+
+    /// Set filter, so that constructor is invoked only for combinations of parents it accepts.
+    pub fn with_filter(mut self, filter: Arc<F::BridgeFilter>) -> Inceptor<F> {
+        self.callbacks.filter = Some(filter);
+        self
+    }
+
+    /// List of parent slots for specified position.
+    fn list(&self, position: usize) -> usize {
+        if self.peers { 0 } else { position }
+    }
+
+    fn slot(&self, position: usize, id: usize) -> &Slot<F> {
+        self.parents[self.list(position)][id]
+            .as_ref()
+            .expect("expected parent to exist at slot")
+    }
+
+    fn find_by_serial(&self, index: usize, serial: usize) -> &AnyParent<F> {
+        let slot = self.parents[self.list(index)]
+            .iter()
+            .filter_map(|slot| slot.as_ref())
+            .find(|slot| slot.serial == serial)
+            .expect("expected to find parent by serial");
+        &slot.parent
+    }
+
+    fn combination(&self, ids: &[usize]) -> Combination<F> {
+        let slots: Vec<&Slot<F>> = ids.iter()
+            .enumerate()
+            .map(|(position, &id)| self.slot(position, id))
+            .collect();
+        Combination {
+            key: slots.iter().map(|slot| slot.serial).collect(),
+            parents: slots.iter().map(|slot| slot.parent.clone()).collect(),
+            addresses: slots.iter().map(|slot| slot.address).collect(),
+        }
+    }
+
+    /// Returns slot ids of all parent combinations that include the parent at `index` `id`,
+    /// and only parents in its group.
+    ///
+    /// Grouped parents are looked up by group hash, ungrouped ones are scanned. Peers are
+    /// combined with every other peer, which takes the first position.
+    fn combinations(&self, index: usize, id: usize) -> Vec<Vec<usize>> {
+        let group = &self.slot(index, id).group;
+        let mut result = vec![Vec::with_capacity(self.edge.parents().len())];
+        for position in 0..self.edge.parents().len() {
+            let list = self.list(position);
+            let slots = &self.parents[list];
+            let own = list == self.list(index);
+            let in_group = |&i: &usize| {
+                !(own && i == id) && slots[i].as_ref().is_some_and(|slot| slot.group == *group)
+            };
+            let is_new = if self.peers { position == 1 } else { position == index };
+            let ids: Vec<usize> = if is_new {
+                vec![id]
+            } else if let Some(ref group) = *group {
+                self.grouped[list]
+                    .get(&group.hash)
+                    .map(|ids| ids.iter().cloned().filter(in_group).collect())
+                    .unwrap_or_default()
+            } else {
+                (0..slots.len()).filter(in_group).collect()
+            };
+            result = result.into_iter()
+                .flat_map(|prefix: Vec<usize>| {
+                    ids.iter().map(move |&i| {
+                        let mut ids = prefix.clone();
+                        ids.push(i);
+                        ids
+                    })
+                })
+                .collect();
+        }
+        result
+    }
+
+    /// Register a parent at specified position (or in the list of peers) and return every
+    /// combination of it with already registered parents at other positions that are in
+    /// the same group.
+    ///
+    /// Returns the id of the parent slot, which should be used to `destroy` it, and the
+    /// combinations that should be bridged.
+    pub fn register(&mut self,
+                    index: usize,
+                    parent: AnyParent<F>,
+                    address: usize,
+                    group: Option<Group<F>>)
+                    -> (usize, Vec<Combination<F>>) {
+        let serial = self.next_serial;
+        self.next_serial += 1;
+        let hash = group.as_ref().map(|group| group.hash);
+
+        let id = insert_into_vec(&mut self.parents[index],
+                                 Slot {
+                                     serial,
+                                     group,
+                                     address,
+                                     parent,
+                                 },
+                                 &mut self.used_sizes[index]);
+        if let Some(hash) = hash {
+            self.grouped[index].entry(hash).or_default().push(id);
+        }
+
+        let combinations = self.combinations(index, id)
+            .iter()
+            .map(|ids| self.combination(ids))
+            .collect();
+        (id, combinations)
+    }
+
+    /// Take ownership of a child created for parents identified by `key`.
+    ///
+    /// If any of the parents was destroyed in the meantime, the child is given back.
+    pub fn adopt(&mut self, key: Vec<usize>, child: Box<F::Child>) -> Option<Box<F::Child>> {
+        let all_alive = key.iter().enumerate().all(|(index, serial)| {
+            self.parents[self.list(index)]
+                .iter()
+                .any(|slot| slot.as_ref().map(|s| s.serial) == Some(*serial))
+        });
+        if !all_alive {
+            return Some(child);
+        }
+        self.children.push((key, ChildCell::new(child)));
+        None
+    }
+
+    /// Remove parent from specified position and give away all children that were created
+    /// for it, in order of construction.
+    pub fn destroy(&mut self, index: usize, id: usize) -> Destroyed<F> {
+        let removed = self.parents[index]
+            .get_mut(id)
+            .unwrap_or_else(|| panic!("expected to find destroy value {:?} at {:?}", id, index))
+            .take()
+            .expect("expected destroyed slot to be used");
+
+        if let Some(ref group) = removed.group {
+            if let Entry::Occupied(mut ids) = self.grouped[index].entry(group.hash) {
+                ids.get_mut().retain(|&other| other != id);
+                if ids.get().is_empty() {
+                    ids.remove();
                 }
             }
         }
-        Ok((id, results))
+
+        truncate_to_used_elements_if_removed_id_is_last(&mut self.parents[index],
+                                                        id,
+                                                        &mut self.used_sizes[index]);
+
+        let (orphans, children): (Vec<_>, Vec<_>) = mem::take(&mut self.children)
+            .into_iter()
+            .partition(|(key, _)| key.contains(&removed.serial));
+        self.children = children;
+
+        let orphans = orphans.into_iter()
+            .map(|(key, child)| {
+                let parents = key.iter()
+                    .enumerate()
+                    .map(|(position, &serial)| if serial == removed.serial {
+                        removed.parent.clone()
+                    } else {
+                        self.find_by_serial(position, serial).clone()
+                    })
+                    .collect();
+                Orphan {
+                    teardown: self.callbacks.teardown.clone(),
+                    parents,
+                    child,
+                }
+            })
+            .collect();
+
+        Destroyed { orphans }
+    }
+
+    /// All children that were created for parent at specified position.
+    fn children_of(&self, index: usize, id: usize) -> Vec<SharedChild<F>> {
+        let serial = self.slot(index, id).serial;
+        self.children
+            .iter()
+            .filter(|&(key, _)| key.contains(&serial))
+            .map(|(_, child)| child.clone())
+            .collect()
+    }
+
+    /// Whether parent at specified position is kept by combinations that are being bridged
+    /// or are deferred.
+    fn is_bridging(&self, index: usize, id: usize) -> bool {
+        Arc::strong_count(&self.slot(index, id).parent) > 1
+    }
+}
+
+/// Bridges a combination deferred while its parents were held.
+type DeferredBridge = Box<dyn FnOnce() -> Result<()>>;
+
+/// Finishes an orphan whose child was locked when it was destroyed.
+type FinishOrphan = Box<dyn FnOnce()>;
+
+thread_local!(
+    /// Addresses of shared parents locked by callbacks running on this thread.
+    static HELD: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// Combinations that were not bridged because this thread held some of their parents,
+    /// each bridged by its closure together with deps and inceptor it was deferred for.
+    static DEFERRED: RefCell<Vec<DeferredBridge>> = const { RefCell::new(Vec::new()) };
+
+    /// Addresses of bridged children locked by `ChildLock` on this thread.
+    static LOCKED_CHILDREN: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// Children destroyed while this thread kept them locked, by their addresses, finished
+    /// once they are unlocked.
+    static UNLOCKED_ORPHANS: RefCell<Vec<(usize, FinishOrphan)>> = const { RefCell::new(Vec::new()) }
+);
+
+fn child_address<F: Flavor>(child: &SharedChild<F>) -> usize {
+    Arc::as_ptr(child) as usize
+}
+
+fn is_child_locked<F: Flavor>(child: &SharedChild<F>) -> bool {
+    LOCKED_CHILDREN.with(|locked| locked.borrow().contains(&child_address(child)))
+}
+
+/// Lock of a bridged child.
+///
+/// If any parent of the child is destroyed while this thread keeps it locked, the child
+/// is torn down after it is unlocked, instead of waiting for this same lock.
+pub struct ChildLock<F: Flavor> {
+    /// Child taken out of `cell`, and put back when the lock is dropped.
+    child: Option<Box<F::Child>>,
+    cell: SharedChild<F>,
+}
+
+/// Lock bridged child, or return `None` if this thread already keeps it locked or it
+/// was dropped.
+pub fn lock_child<F: Flavor>(cell: SharedChild<F>) -> Option<ChildLock<F>> {
+    if is_child_locked(&cell) {
+        return None;
+    }
+    let child = cell.take()?;
+    LOCKED_CHILDREN.with(|locked| locked.borrow_mut().push(child_address(&cell)));
+    Some(ChildLock {
+        child: Some(child),
+        cell,
+    })
+}
+
+impl<F: Flavor> Deref for ChildLock<F> {
+    type Target = F::Child;
+
+    fn deref(&self) -> &F::Child {
+        self.child.as_ref().expect("expected child to stay locked")
+    }
+}
+
+impl<F: Flavor> DerefMut for ChildLock<F> {
+    fn deref_mut(&mut self) -> &mut F::Child {
+        self.child.as_mut().expect("expected child to stay locked")
     }
+}
 
-    pub fn incept_2(&mut self, parent: Arc<Mutex<T2>>) -> Result<(usize, Vec<Box<Any>>)> {
-        let id = insert_into_vec(&mut self.d2, parent, &mut self.used_size2);
-        let mut results = Vec::new();
-        for i1 in 0..self.d1.len() {
-            if !self.d1[i1].is_none() {
-                match try!(self.invoke(i1, id)) {
-                    Some(res) => results.push(res),
-                    None => (),
+impl<F: Flavor> Drop for ChildLock<F> {
+    fn drop(&mut self) {
+        self.cell.put(self.child.take());
+        let address = child_address(&self.cell);
+        LOCKED_CHILDREN.with(|locked| {
+            let mut locked = locked.borrow_mut();
+            if let Some(position) = locked.iter().rposition(|&other| other == address) {
+                locked.remove(position);
+            }
+        });
+        let orphans: Vec<(usize, Box<dyn FnOnce()>)> = UNLOCKED_ORPHANS.with(|orphans| {
+            let (unlocked, locked) = mem::take(&mut *orphans.borrow_mut())
+                .into_iter()
+                .partition(|&(child, _)| child == address);
+            *orphans.borrow_mut() = locked;
+            unlocked
+        });
+        for (_, finish) in orphans {
+            finish();
+        }
+    }
+}
+
+/// Marks shared parents as locked by this thread for as long as it lives.
+pub struct Hold {
+    addresses: Vec<usize>,
+}
+
+/// Mark shared parents with specified mutex addresses as locked by this thread.
+///
+/// Combinations with held parents are deferred until `run_deferred`, instead of
+/// deadlocking on them.
+pub fn hold(addresses: &[usize]) -> Hold {
+    HELD.with(|held| held.borrow_mut().extend_from_slice(addresses));
+    Hold { addresses: addresses.to_vec() }
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        // Values locked by users may be released in any order.
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            for address in &self.addresses {
+                if let Some(position) = held.iter().rposition(|other| other == address) {
+                    held.remove(position);
                 }
             }
+        });
+    }
+}
+
+/// Hold of a value locked by user, that bridges the combinations it deferred once
+/// the value is released.
+pub struct Release {
+    hold: Option<Hold>,
+}
+
+/// Mark shared value with specified mutex address as locked by user on this thread.
+pub fn hold_until_released(address: usize) -> Release {
+    Release { hold: Some(hold(&[address])) }
+}
+
+impl Release {
+    /// Stop holding the value, which must be already unlocked, and bridge deferred
+    /// combinations if this thread holds nothing else.
+    pub fn release(mut self) -> Result<()> {
+        self.hold = None;
+        run_deferred()
+    }
+}
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        if self.hold.take().is_some() {
+            // Errors can only be returned by `release`.
+            let _ = run_deferred();
+        }
+    }
+}
+
+fn is_held(addresses: &[usize]) -> bool {
+    HELD.with(|held| {
+        let held = held.borrow();
+        addresses.iter().any(|address| held.contains(address))
+    })
+}
+
+/// Number of combinations deferred on this thread, to `discard_deferred` the ones deferred
+/// after it.
+pub fn deferred_mark() -> usize {
+    DEFERRED.with(|deferred| deferred.borrow().len())
+}
+
+/// Drop combinations deferred on this thread after `mark` without bridging them.
+///
+/// Deferred combinations keep their parents alive, so the ones deferred while a value was
+/// created must be dropped before that value is taken back out of its lock.
+pub fn discard_deferred(mark: usize) {
+    let discarded = DEFERRED.with(|deferred| {
+        let mut deferred = deferred.borrow_mut();
+        let mark = mark.min(deferred.len());
+        deferred.split_off(mark)
+    });
+    // Parents may be dropped with their last combination, so they are dropped only after
+    // the list is released.
+    mem::drop(discarded);
+}
+
+/// Bridge deferred combinations, if this thread does not hold any parents anymore.
+///
+/// Every deferred combination is bridged or dropped, even if some of them fail, so that
+/// none of them keeps its parents shared. Returns the first error, with the rest as
+/// its `Error::others`.
+pub fn run_deferred() -> Result<()> {
+    if HELD.with(|held| !held.borrow().is_empty()) {
+        return Ok(());
+    }
+    let mut errors = Vec::new();
+    while let Some(deferred) = DEFERRED.with(|deferred| deferred.borrow_mut().pop()) {
+        if let Err(e) = deferred() {
+            errors.push(e);
         }
-        Ok((id, results))
     }
+    Error::collect(errors)
+}
+
+/// Register the parent at specified position of the inceptor, and bridge it with all
+/// registered parents.
+///
+/// Returns the `Destructor` that should be kept for as long as the parent lives. If
+/// any constructor fails, the parent and children bridged so far are destroyed.
+pub fn incept<F: Flavor>(inceptor: &Arc<Mutex<Inceptor<F>>>,
+                         deps: &Deps<F>,
+                         index: usize,
+                         parent: AnyParent<F>,
+                         address: usize)
+                         -> Result<Destructor<F>> {
+    let callbacks = inceptor.lock().expect("failed to lock inceptor").callbacks.clone();
+    let group = callbacks.group.and_then(|group| group(index, F::any(&*parent)));
 
-    pub fn destroy_1(&mut self, id: usize) {
-        let mut tmp: Option<Arc<Mutex<T1>>> = None;
-        mem::swap(&mut tmp,
-                  self.d1
-                      .get_mut(id)
-                      .expect(&format!("expected to find destroy_1 value {:?}", id)));
+    let (id, combinations) = inceptor.lock()
+        .expect("failed to lock inceptor")
+        .register(index, parent, address, group);
+    let destructor = Destructor::new(inceptor.clone(), index, id);
 
-        truncate_to_used_elements_if_removed_id_is_last(&mut self.d1, id, &mut self.used_size1);
+    for combination in combinations {
+        bridge(deps, inceptor, combination)?;
+    }
+    Ok(destructor)
+}
+
+/// Invoke constructor for combination of parents and let inceptor adopt its child.
+///
+/// If this thread holds any of the parents, the combination is deferred.
+fn bridge<F: Flavor>(deps: &Deps<F>, inceptor: &Arc<Mutex<Inceptor<F>>>, combination: Combination<F>) -> Result<()> {
+    if is_held(&combination.addresses) {
+        let (deps, inceptor) = (deps.handle(), inceptor.clone());
+        let deferred: Box<dyn FnOnce() -> Result<()>> = Box::new(move || bridge(&deps, &inceptor, combination));
+        DEFERRED.with(|list| list.borrow_mut().push(deferred));
+        return Ok(());
     }
 
-    pub fn destroy_2(&mut self, id: usize) {
-        let mut tmp: Option<Arc<Mutex<T2>>> = None;
-        mem::swap(&mut tmp,
-                  self.d2
-                      .get_mut(id)
-                      .expect(&format!("expected to find destroy_2 value {:?}", id)));
+    let callbacks = inceptor.lock().expect("failed to lock inceptor").callbacks.clone();
+    let value = {
+        let _hold = hold(&combination.addresses);
+        let parents = combination.parents();
+        match callbacks.filter {
+            Some(ref filter) if !filter(&parents) => None,
+            _ => (callbacks.constructor)(deps, &parents)?,
+        }
+    };
 
-        truncate_to_used_elements_if_removed_id_is_last(&mut self.d2, id, &mut self.used_size2);
+    if let Some(value) = value {
+        let child = callbacks.child.expect("expected inceptor to create child dependencies");
+        let child = child(deps, value)?;
+        let orphan = inceptor.lock()
+            .expect("failed to lock inceptor")
+            .adopt(combination.key.clone(), child);
+        // One of the parents was destroyed while child was created.
+        if let Some(mut orphan) = orphan {
+            if let Some(ref teardown) = callbacks.teardown {
+                teardown(&combination.parents(), &mut *orphan);
+            }
+        }
     }
+
+    run_deferred()
 }
 
-fn insert_into_vec<T>(data: &mut Vec<Option<Arc<Mutex<T>>>>,
-                      value: Arc<Mutex<T>>,
-                      used_size: &mut usize)
-                      -> usize {
+fn insert_into_vec<T>(data: &mut Vec<Option<T>>, value: T, used_size: &mut usize) -> usize {
     for (i, item) in data.iter_mut().enumerate() {
         if item.is_none() {
-            let mut tmp = Some(value);
-            mem::swap(&mut tmp, item);
+            *item = Some(value);
             if i + 1 > *used_size {
                 *used_size = i + 1;
             }
@@ -134,7 +747,7 @@ fn insert_into_vec<T>(data: &mut Vec<Option<Arc<Mutex<T>>>>,
     index
 }
 
-fn truncate_to_used_elements_if_removed_id_is_last<T>(data: &mut Vec<Option<Arc<Mutex<T>>>>,
+fn truncate_to_used_elements_if_removed_id_is_last<T>(data: &mut Vec<Option<T>>,
                                                       removed_id: usize,
                                                       used_size: &mut usize) {
     if removed_id + 1 != *used_size {
@@ -142,117 +755,378 @@ fn truncate_to_used_elements_if_removed_id_is_last<T>(data: &mut Vec<Option<Arc<
     }
     while *used_size > 0 {
         *used_size -= 1;
-        if *used_size == 0 || !data[*used_size - 1].is_none() {
+        if *used_size == 0 || data[*used_size - 1].is_some() {
             break;
         }
     }
     data.truncate(*used_size);
 }
 
-pub struct Destructor<T1: Any, T2: Any> {
+pub struct Destructor<F: Flavor> {
     /// Inceptor to clean
-    inceptor: Arc<Mutex<Inceptor<T1, T2>>>,
-    /// Which method, 1 or 2
+    inceptor: Arc<Mutex<Inceptor<F>>>,
+    /// Parent position in inceptor
     index: usize,
     /// Id to clean
     id: usize,
 }
 
-impl<T1: Any, T2: Any> Destructor<T1, T2> {
-    pub fn new(inceptor: Arc<Mutex<Inceptor<T1, T2>>>,
-               index: usize,
-               id: usize)
-               -> Destructor<T1, T2> {
+impl<F: Flavor> Destructor<F> {
+    pub fn new(inceptor: Arc<Mutex<Inceptor<F>>>, index: usize, id: usize) -> Destructor<F> {
         Destructor {
-            inceptor: inceptor,
-            index: index,
-            id: id,
+            inceptor,
+            index,
+            id,
         }
     }
+
+    fn children(&self) -> (Edge, Vec<SharedChild<F>>) {
+        let inceptor = self.inceptor.lock().expect("failed to lock");
+        (inceptor.edge.clone(), inceptor.children_of(self.index, self.id))
+    }
+
+    /// Whether the parent is still shared with bridges that are not done with it.
+    pub fn is_bridging(&self) -> bool {
+        self.inceptor.lock().expect("failed to lock").is_bridging(self.index, self.id)
+    }
 }
 
-impl<T1: Any, T2: Any> Drop for Destructor<T1, T2> {
-    fn drop(&mut self) {
-        if self.index == 1 {
-            self.inceptor.lock().expect("failed to lock").destroy_1(self.id);
-        } else if self.index == 2 {
-            self.inceptor.lock().expect("failed to lock").destroy_2(self.id);
+impl<F: Flavor> Child<F> for Destructor<F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        let (edge, children) = self.children();
+        let info = BridgeInfo::new(edge, self.index);
+        visitor.enter_bridge(&info);
+        // Children this thread keeps locked are skipped, instead of waiting for themselves.
+        for child in children.into_iter().filter_map(lock_child) {
+            child.accept(visitor);
         }
+        visitor.leave_bridge(&info);
+    }
+
+    fn bridged(&self) -> Vec<SharedChild<F>> {
+        self.children().1
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<F: Flavor> Drop for Destructor<F> {
+    fn drop(&mut self) {
+        let destroyed = self.inceptor.lock().expect("failed to lock").destroy(self.index, self.id);
+        // Children are torn down and dropped only after the inceptor is unlocked, because
+        // they may contain destructors for this same inceptor.
+        destroyed.finish();
     }
 }
 
 #[cfg(test)]
 mod test {
     use std::any::Any;
-    use std::sync::{Arc, Mutex};
+    use std::sync::{Arc, Mutex, Weak};
+    use flavor::Threaded;
+    use graph::{Edge, EdgeKind, Node};
+    use Deps;
     use super::*;
 
-    fn count_not_none<T>(data: &mut Vec<Option<Arc<Mutex<T>>>>) -> usize {
-        data.iter().filter(|v| !v.is_none()).count()
+    fn edge(arity: usize) -> Edge {
+        Edge::new(EdgeKind::Bridge, vec![Node::of::<i32>(); arity], None)
+    }
+
+    fn count_not_none<T>(data: &[Option<T>]) -> usize {
+        data.iter().filter(|v| v.is_some()).count()
+    }
+
+    fn constructor<C>(constructor: C) -> Arc<<Threaded as Flavor>::BridgeConstructor>
+        where C: Fn(&Deps, &[&dyn Any]) -> Result<Option<Box<dyn Any>>> + 'static + Send + Sync
+    {
+        Arc::new(constructor)
+    }
+
+    fn ic_with_val(arity: usize) -> Inceptor<Threaded> {
+        Inceptor::new(edge(arity), constructor(|_deps, _parents| Ok(Some(Box::new(42)))))
+            .with_child(|_deps, value| Ok(probe(*value.downcast::<i32>().unwrap())))
+    }
+
+    fn parent<T: Any + Send>(value: T) -> AnyParent<Threaded> {
+        Arc::new(Arc::new(Mutex::new(value)))
+    }
+
+    /// Register parent, grouped by the inceptor grouping.
+    fn register(ic: &mut Inceptor<Threaded>,
+                index: usize,
+                parent: AnyParent<Threaded>)
+                -> (usize, Vec<Combination<Threaded>>) {
+        let group = ic.callbacks.group.clone().and_then(|group| group(index, Threaded::any(&*parent)));
+        let address = &*parent as *const _ as *const () as usize;
+        ic.register(index, parent, address, group)
+    }
+
+    /// Adopted child that does not visit anything.
+    struct Probe(i32);
+
+    impl Child<Threaded> for Probe {
+        fn accept(&self, _visitor: &mut dyn Visitor) {}
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
     }
 
-    fn ic_with_val<A: Any, B: Any>() -> Inceptor<A, B> {
-        Inceptor::<A, B>::new(move |_a, _b| Ok(Some(Box::new(42))))
+    fn probe(value: i32) -> Box<dyn Child<Threaded> + Send + Sync> {
+        Box::new(Probe(value))
     }
 
     #[test]
     fn should_register_and_destroy_first_arg() {
-        let mut ic = ic_with_val::<i32, bool>();
-        let (id, instances) = ic.incept_1(Arc::new(Mutex::new(11)))
-            .expect("failed to incept first arg");
+        let mut ic = ic_with_val(2);
+        let (id, combinations) = register(&mut ic, 0, parent(11));
 
-        assert_eq!(instances.len(), 0);
-        assert_eq!(count_not_none(&mut ic.d1), 1);
+        assert_eq!(combinations.len(), 0);
+        assert_eq!(count_not_none(&ic.parents[0]), 1);
 
-        ic.destroy_1(id);
+        ic.destroy(0, id);
 
-        assert_eq!(count_not_none(&mut ic.d1), 0);
+        assert_eq!(count_not_none(&ic.parents[0]), 0);
     }
 
     #[test]
     fn should_register_and_destroy_second_arg() {
-        let mut ic = ic_with_val::<i32, bool>();
-        let (id, instances) = ic.incept_2(Arc::new(Mutex::new(false)))
-            .expect("failed to incept first arg");
+        let mut ic = ic_with_val(2);
+        let (id, combinations) = register(&mut ic, 1, parent(false));
 
-        assert_eq!(instances.len(), 0);
-        assert_eq!(count_not_none(&mut ic.d2), 1);
+        assert_eq!(combinations.len(), 0);
+        assert_eq!(count_not_none(&ic.parents[1]), 1);
 
-        ic.destroy_2(id);
+        ic.destroy(1, id);
 
-        assert_eq!(count_not_none(&mut ic.d2), 0);
+        assert_eq!(count_not_none(&ic.parents[1]), 0);
     }
 
     #[test]
-    fn should_create_and_destroy_instances_for_all_existing_items() {
-        let mut ic = ic_with_val::<i32, bool>();
+    fn should_combine_and_destroy_all_existing_items() {
+        let mut ic = ic_with_val(2);
         let mut value_num_3 = Arc::new(Mutex::new(3));
-        let (_, _) = ic.incept_1(Arc::new(Mutex::new(1))).unwrap();
-        let (_, _) = ic.incept_1(Arc::new(Mutex::new(2))).unwrap();
-        let (id3, _) = ic.incept_1(value_num_3.clone()).unwrap();
-        assert_eq!(count_not_none(&mut ic.d1), 3);
+        register(&mut ic, 0, parent(1));
+        register(&mut ic, 0, parent(2));
+        let (id3, _) = register(&mut ic, 0, Arc::new(value_num_3.clone()));
+        assert_eq!(count_not_none(&ic.parents[0]), 3);
 
-        let (other_id, instances) = ic.incept_2(Arc::new(Mutex::new(false))).unwrap();
-        assert_eq!(instances.len(), 3);
-        assert_eq!(count_not_none(&mut ic.d2), 1);
+        let (other_id, combinations) = register(&mut ic, 1, parent(false));
+        assert_eq!(combinations.len(), 3);
+        assert_eq!(count_not_none(&ic.parents[1]), 1);
+        drop(combinations);
 
         // should not be possible to take out value from arc
         value_num_3 = Arc::try_unwrap(value_num_3).unwrap_err();
-        // destroying parent should free up the instance that was created using it
-        ic.destroy_1(id3);
-        assert_eq!(count_not_none(&mut ic.d1), 2);
+        // destroying parent should free up the arc
+        ic.destroy(0, id3);
+        assert_eq!(count_not_none(&ic.parents[0]), 2);
         // should be possible to take out value from arc
         {
             let val = Arc::try_unwrap(value_num_3).expect("expected arc refcount 1");
             assert_eq!(3, *val.lock().unwrap());
         }
         // memory should be freed
-        assert_eq!(ic.d1.len(), 2);
+        assert_eq!(ic.parents[0].len(), 2);
 
-        ic.destroy_2(other_id);
-        assert_eq!(count_not_none(&mut ic.d2), 0);
+        ic.destroy(1, other_id);
+        assert_eq!(count_not_none(&ic.parents[1]), 0);
 
         // memory should be freed
-        assert_eq!(ic.d2.len(), 0);
+        assert_eq!(ic.parents[1].len(), 0);
+    }
+
+    #[test]
+    fn should_return_all_combinations() {
+        let mut ic = ic_with_val(3);
+        register(&mut ic, 0, parent(1));
+        register(&mut ic, 0, parent(2));
+        register(&mut ic, 1, parent(false));
+        register(&mut ic, 1, parent(true));
+
+        let (_, combinations) = register(&mut ic, 2, parent("c"));
+        assert_eq!(combinations.len(), 4);
+
+        let (_, combinations) = register(&mut ic, 0, parent(3));
+        assert_eq!(combinations.len(), 2);
+    }
+
+    #[test]
+    fn should_return_adopted_children_when_any_parent_is_destroyed() {
+        let mut ic = ic_with_val(2);
+        let (id1, _) = register(&mut ic, 0, parent(1));
+        let (id2, combinations) = register(&mut ic, 1, parent(false));
+        for combination in combinations {
+            assert!(ic.adopt(combination.key, probe(0)).is_none());
+        }
+        let (id3, combinations) = register(&mut ic, 1, parent(true));
+        for combination in combinations {
+            assert!(ic.adopt(combination.key, probe(0)).is_none());
+        }
+
+        assert_eq!(ic.destroy(1, id2).orphans.len(), 1);
+        assert_eq!(ic.destroy(0, id1).orphans.len(), 1);
+        assert_eq!(ic.destroy(1, id3).orphans.len(), 0);
+    }
+
+    #[test]
+    fn should_invoke_teardown_with_parents_of_destroyed_child() {
+        let torn_down = Arc::new(Mutex::new(Vec::new()));
+        let mut ic = ic_with_val(2).with_teardown(Arc::new({
+            let torn_down = torn_down.clone();
+            move |parents: &[&dyn Any], child: &mut (dyn Child<Threaded> + Send + Sync)| {
+                let p1 = *parents[0].downcast_ref::<Arc<Mutex<i32>>>().unwrap().lock().unwrap();
+                let p2 = *parents[1].downcast_ref::<Arc<Mutex<i32>>>().unwrap().lock().unwrap();
+                let child = child.as_any().downcast_ref::<Probe>().unwrap().0;
+                torn_down.lock().unwrap().push((p1, p2, child));
+            }
+        }));
+        let (id1, _) = register(&mut ic, 0, parent(1));
+        for value in 2..4 {
+            let (_, combinations) = register(&mut ic, 1, parent(value));
+            for combination in combinations {
+                assert!(ic.adopt(combination.key, probe(value * 10)).is_none());
+            }
+        }
+
+        let destroyed = ic.destroy(0, id1);
+        assert!(torn_down.lock().unwrap().is_empty());
+        destroyed.finish();
+        assert_eq!(vec![(1, 3, 30), (1, 2, 20)], *torn_down.lock().unwrap());
+    }
+
+    #[test]
+    fn should_remove_parent_if_constructor_fails() {
+        let deps = Deps::new();
+        let ic = Arc::new(Mutex::new(Inceptor::new(edge(2), constructor(|_deps, parents| {
+            let p1 = *parents[0].downcast_ref::<Arc<Mutex<i32>>>().unwrap().lock().unwrap();
+            if p1 > 1 {
+                return Err(::Error::new("too large"));
+            }
+            Ok(None)
+        }))));
+        let _d1 = incept(&ic, &deps, 0, parent(1), 1).unwrap();
+        let _d2 = incept(&ic, &deps, 0, parent(2), 2).unwrap();
+
+        assert!(incept(&ic, &deps, 1, parent(false), 3).is_err());
+        assert_eq!(count_not_none(&ic.lock().unwrap().parents[1]), 0);
+        assert_eq!(ic.lock().unwrap().parents[1].len(), 0);
+    }
+
+    #[test]
+    fn should_invoke_constructor_while_unlocked() {
+        let deps = Deps::new();
+        let ic = Arc::new_cyclic(|weak: &Weak<Mutex<Inceptor<Threaded>>>| {
+            let weak = weak.clone();
+            Mutex::new(Inceptor::new(edge(2), constructor(move |_deps, _parents| {
+                    let ic = weak.upgrade().unwrap();
+                    let _locked = ic.try_lock().expect("expected inceptor to be unlocked");
+                    Ok(Some(Box::new(7)))
+                }))
+                .with_child(|_deps, value| Ok(probe(*value.downcast::<i32>().unwrap()))))
+        });
+
+        let _d1 = incept(&ic, &deps, 0, parent(1), 1).unwrap();
+        let _d2 = incept(&ic, &deps, 1, parent(2), 2).unwrap();
+        assert_eq!(ic.lock().unwrap().children.len(), 1);
+    }
+
+    #[test]
+    fn should_give_back_child_of_destroyed_parent() {
+        let mut ic = ic_with_val(2);
+        let (id, _) = register(&mut ic, 0, parent(1));
+        let (_, mut combinations) = register(&mut ic, 1, parent(false));
+        let combination = combinations.pop().unwrap();
+
+        ic.destroy(0, id);
+
+        assert!(ic.adopt(combination.key, probe(0)).is_some());
+    }
+
+    #[test]
+    fn should_not_lock_child_that_was_dropped() {
+        let mut ic = ic_with_val(2);
+        let (id, _) = register(&mut ic, 0, parent(1));
+        let (_, combinations) = register(&mut ic, 1, parent(false));
+        for combination in combinations {
+            assert!(ic.adopt(combination.key, probe(5)).is_none());
+        }
+        let child = ic.children[0].1.clone();
+        {
+            let locked = lock_child(child.clone()).unwrap();
+            assert_eq!(5, locked.as_any().downcast_ref::<Probe>().unwrap().0);
+            assert!(lock_child(child.clone()).is_none());
+        }
+
+        ic.destroy(0, id).finish();
+
+        assert!(lock_child(child).is_none());
+    }
+
+    #[test]
+    fn should_only_combine_parents_in_same_group() {
+        let mut ic = ic_with_val(2).with_group(Arc::new(|_: usize, parent: &dyn Any| {
+            let value = *parent.downcast_ref::<Arc<Mutex<i32>>>().unwrap().lock().unwrap();
+            Some(Group::new(value % 2))
+        }));
+        let (id1, _) = register(&mut ic, 0, parent(1));
+        register(&mut ic, 0, parent(2));
+        register(&mut ic, 0, parent(3));
+
+        let (_, combinations) = register(&mut ic, 1, parent(5));
+        assert_eq!(combinations.len(), 2);
+
+        ic.destroy(0, id1);
+        assert_eq!(ic.grouped[0][&Group::<Threaded>::new(1).hash], vec![2]);
+
+        let (_, combinations) = register(&mut ic, 1, parent(7));
+        assert_eq!(combinations.len(), 1);
+        let (_, combinations) = register(&mut ic, 1, parent(4));
+        assert_eq!(combinations.len(), 1);
+    }
+
+    #[test]
+    fn should_only_invoke_constructor_for_accepted_parents() {
+        let deps = Deps::new();
+        let ic = Arc::new(Mutex::new(ic_with_val(2).with_filter(Arc::new(|parents: &[&dyn Any]| {
+            let p1 = *parents[0].downcast_ref::<Arc<Mutex<i32>>>().unwrap().lock().unwrap();
+            let p2 = *parents[1].downcast_ref::<Arc<Mutex<bool>>>().unwrap().lock().unwrap();
+            p2 || p1 > 1
+        }))));
+        let _d1 = incept(&ic, &deps, 0, parent(1), 1).unwrap();
+        let _d2 = incept(&ic, &deps, 0, parent(2), 2).unwrap();
+
+        let _d3 = incept(&ic, &deps, 1, parent(false), 3).unwrap();
+        assert_eq!(ic.lock().unwrap().children.len(), 1);
+        let _d4 = incept(&ic, &deps, 1, parent(true), 4).unwrap();
+        assert_eq!(ic.lock().unwrap().children.len(), 3);
+    }
+
+    #[test]
+    fn should_combine_distinct_peers_once() {
+        let mut ic = ic_with_val(2).with_peers();
+        let (id1, combinations) = register(&mut ic, 0, parent(1));
+        assert_eq!(combinations.len(), 0);
+        let (id2, combinations) = register(&mut ic, 0, parent(2));
+        assert_eq!(combinations.len(), 1);
+        let (_, combinations) = register(&mut ic, 0, parent(3));
+        assert_eq!(combinations.len(), 2);
+
+        for combination in combinations {
+            assert!(ic.adopt(combination.key, probe(0)).is_none());
+        }
+        assert_eq!(ic.destroy(0, id1).orphans.len(), 1);
+        assert_eq!(ic.destroy(0, id2).orphans.len(), 1);
+        assert_eq!(ic.children.len(), 0);
     }
 }
@@ -0,0 +1,183 @@
+//! Inspection of live scopes.
+
+use std::any::Any;
+use std::fmt;
+use flavor::{Flavor, Threaded};
+use graph::Edge;
+use inceptor::SharedChild;
+
+/// How the value of a scope is owned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Sharing {
+    /// Value is owned by scope directly.
+    Isolated,
+    /// Value is shared with bridges in `Arc<Mutex<T>>` or `Arc<RwLock<T>>`, or in
+    /// `Rc<RefCell<T>>` by `LocalDeps`.
+    Shared,
+}
+
+/// Scope visited by `Visitor`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ScopeInfo {
+    name: &'static str,
+    sharing: Sharing,
+}
+
+impl ScopeInfo {
+    pub fn new(name: &'static str, sharing: Sharing) -> ScopeInfo {
+        ScopeInfo {
+            name,
+            sharing,
+        }
+    }
+
+    /// Type name of scope value.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn sharing(&self) -> Sharing {
+        self.sharing
+    }
+}
+
+/// Bridge registration of a scope value, visited by `Visitor`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BridgeInfo {
+    edge: Edge,
+    position: usize,
+}
+
+impl BridgeInfo {
+    pub fn new(edge: Edge, position: usize) -> BridgeInfo {
+        BridgeInfo {
+            edge,
+            position,
+        }
+    }
+
+    /// Bridge registration.
+    pub fn edge(&self) -> &Edge {
+        &self.edge
+    }
+
+    /// Position of the scope value among bridge parents.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+/// Receives every scope and bridge of inspected scope tree, parents before children.
+///
+/// Children of a scope are visited between `enter_scope` and `leave_scope` calls, and
+/// the bridged children, that are alive while the scope value is, between `enter_bridge`
+/// and `leave_bridge`.
+pub trait Visitor {
+    fn enter_scope(&mut self, scope: &ScopeInfo);
+
+    fn leave_scope(&mut self, _scope: &ScopeInfo) {}
+
+    fn enter_bridge(&mut self, _bridge: &BridgeInfo) {}
+
+    fn leave_bridge(&mut self, _bridge: &BridgeInfo) {}
+}
+
+/// Child kept alive by scope of flavor `F`.
+pub trait Child<F: Flavor>: Any {
+    fn accept(&self, visitor: &mut dyn Visitor);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Children owned by this child.
+    fn children(&self) -> &[Box<F::Child>] {
+        &[]
+    }
+
+    fn children_mut(&mut self) -> &mut [Box<F::Child>] {
+        &mut []
+    }
+
+    /// Bridged children, that are shared with other bridge parents and can only be reached
+    /// through `inceptor::lock_child`.
+    fn bridged(&self) -> Vec<SharedChild<F>> {
+        Vec::new()
+    }
+}
+
+/// Print child tree the same as `TreePrinter`.
+fn fmt_child<F: Flavor, C: ?Sized + Child<F>>(child: &C, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut printer = TreePrinter::new();
+    child.accept(&mut printer);
+    f.write_str(printer.as_str().trim_end())
+}
+
+impl<F: Flavor> fmt::Debug for dyn Child<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_child(self, f)
+    }
+}
+
+impl fmt::Debug for dyn Child<Threaded> + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_child(self, f)
+    }
+}
+
+/// Visitor that prints scope tree, one scope or bridge per line, indented by depth.
+#[derive(Debug, Default)]
+pub struct TreePrinter {
+    out: String,
+    depth: usize,
+}
+
+impl TreePrinter {
+    pub fn new() -> TreePrinter {
+        TreePrinter {
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.out
+    }
+
+    pub fn into_string(self) -> String {
+        self.out
+    }
+
+    fn line(&mut self, line: fmt::Arguments) {
+        for _ in 0..self.depth {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(&line.to_string());
+        self.out.push('\n');
+    }
+}
+
+impl Visitor for TreePrinter {
+    fn enter_scope(&mut self, scope: &ScopeInfo) {
+        let sharing = match scope.sharing() {
+            Sharing::Isolated => "isolated",
+            Sharing::Shared => "shared",
+        };
+        self.line(format_args!("{} [{}]", scope.name(), sharing));
+        self.depth += 1;
+    }
+
+    fn leave_scope(&mut self, _scope: &ScopeInfo) {
+        self.depth -= 1;
+    }
+
+    fn enter_bridge(&mut self, bridge: &BridgeInfo) {
+        let parents: Vec<&str> = bridge.edge().parents().iter().map(|p| p.name()).collect();
+        self.line(format_args!("bridge #{} of ({})", bridge.position(), parents.join(", ")));
+        self.depth += 1;
+    }
+
+    fn leave_bridge(&mut self, _bridge: &BridgeInfo) {
+        self.depth -= 1;
+    }
+}
@@ -5,17 +5,35 @@
 //!
 
 mod deps;
+mod flavor;
 mod collection;
 mod scope;
 mod inceptor;
 mod constructed;
+mod error;
+mod cycles;
+mod graph;
+mod inspect;
+mod locks;
+mod local;
+mod order;
 
 use std::result;
-use std::error;
 
-pub use constructed::MaybeMutexGuard;
+pub use constructed::{MaybeMutexGuard, MaybeReadGuard};
 pub use collection::Collection;
-pub use scope::Scope;
-pub use deps::Deps;
+pub use scope::{ChildMut, ChildRef, Scope};
+pub use flavor::{Flavor, Local, Threaded};
+pub use local::{LocalDeps, LocalScope};
+pub use error::{Error, ErrorKind, CreateError, ExplodeError, ExplodeReason};
+pub use graph::{Graph, Edge, EdgeKind, Node};
+pub use inspect::{Visitor, ScopeInfo, BridgeInfo, Sharing, TreePrinter};
+pub use locks::{LockInversion, SharedLock};
+pub use order::Order;
 
-pub type Result<T> = result::Result<T, Box<error::Error>>;
+pub type Result<T> = result::Result<T, Error>;
+
+/// Dependencies of values that can be created and used from any thread.
+///
+/// See `LocalDeps` for values that stay on a single thread.
+pub type Deps = deps::Deps<Threaded>;
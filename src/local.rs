@@ -0,0 +1,367 @@
+//! Dependencies of values that stay on a single thread.
+//!
+//! `LocalDeps` has the same registrations as `Deps`, but neither callbacks nor values have to
+//! be `Send` or `Sync`, so they can hold `Rc` and `RefCell`. Values that take part in bridges are
+//! shared in `Rc<RefCell<T>>` instead of a lock, and `LocalDeps` and `LocalScope` can not leave
+//! the thread they were created on.
+
+use std::any::Any;
+use std::hash::Hash;
+use std::result;
+use deps::Deps;
+use flavor::Local;
+use {CreateError, Order, Result, Scope};
+
+/// Single-threaded `Deps`, whose registrations have no `Send + Sync` bounds.
+///
+/// Callbacks receive `LocalDeps` that are creating the value.
+///
+/// ```compile_fail
+/// use std::thread;
+///
+/// let deps = di::LocalDeps::new();
+/// thread::spawn(move || drop(deps));
+/// ```
+pub type LocalDeps = Deps<Local>;
+
+/// Single-threaded `Scope`, created by `LocalDeps`.
+///
+/// Value that takes part in bridges is shared in `RefCell`, so `lock` and `read` panic
+/// instead of blocking if it is already borrowed by the same thread.
+///
+/// ```compile_fail
+/// use std::thread;
+///
+/// let scope = di::LocalDeps::new().create(1).unwrap();
+/// thread::spawn(move || drop(scope));
+/// ```
+pub type LocalScope<T> = Scope<T, Local>;
+
+impl Default for LocalDeps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalDeps {
+    pub fn new() -> LocalDeps {
+        Deps::with_registry()
+    }
+
+    pub fn when_ready<T, F>(&mut self, action: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&LocalDeps, &mut T) -> Result<()> + 'static
+    {
+        self.when_ready_ordered(Order::new(), action).expect("expected unordered registration to succeed")
+    }
+
+    /// See `Deps::when_ready_ordered`.
+    pub fn when_ready_ordered<T, F>(&mut self, order: Order, action: F) -> Result<()>
+        where T: 'static + Any,
+              F: for<'r> Fn(&LocalDeps, &mut T) -> Result<()> + 'static
+    {
+        self.register_when_ready(order, action)
+    }
+
+    /// See `Deps::decorate`.
+    pub fn decorate<T, F>(&mut self, decorator: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&LocalDeps, T) -> result::Result<T, CreateError<T>> + 'static
+    {
+        self.register_decorate(decorator)
+    }
+
+    /// See `Deps::before_create`.
+    pub fn before_create<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&LocalDeps, &mut T) + 'static
+    {
+        self.register_before_create(hook)
+    }
+
+    /// See `Deps::validate`.
+    pub fn validate<T, F>(&mut self, check: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&LocalDeps, &T) -> Result<()> + 'static
+    {
+        self.register_validate(check)
+    }
+
+    /// See `Deps::when_dropping`.
+    pub fn when_dropping<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&mut T) + 'static
+    {
+        self.register_when_dropping(hook)
+    }
+
+    /// See `Deps::when_dropped`.
+    pub fn when_dropped<T, F>(&mut self, hook: F)
+        where T: 'static + Any,
+              F: for<'r> Fn(&mut T) + 'static
+    {
+        self.register_when_dropped(hook)
+    }
+
+    /// See `Deps::attach`.
+    pub fn attach<P, C, F>(&mut self, constructor: F)
+        where P: 'static + Any, // Parent
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P) -> Result<C> + 'static
+    {
+        self.attach_ordered(Order::new(), constructor).expect("expected unordered registration to succeed")
+    }
+
+    /// See `Deps::attach_ordered`.
+    pub fn attach_ordered<P, C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where P: 'static + Any, // Parent
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P) -> Result<C> + 'static
+    {
+        self.register_attach(order, constructor)
+    }
+
+    /// See `Deps::bridge`.
+    pub fn bridge<P1, P2, C, F>(&mut self, constructor: F)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static
+    {
+        self.bridge_ordered(Order::new(), constructor).expect("expected unordered registration to succeed")
+    }
+
+    /// See `Deps::bridge_ordered`.
+    pub fn bridge_ordered<P1, P2, C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static
+    {
+        self.register_bridge(order, constructor)
+    }
+
+    /// See `Deps::bridge_within`.
+    pub fn bridge_within<S, P1, P2, C, F>(&mut self, constructor: F)
+        where S: 'static + Any, // Scope
+              P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static
+    {
+        self.register_bridge_within::<S, P1, P2, C, _>(constructor)
+    }
+
+    /// See `Deps::bridge_by_key`.
+    pub fn bridge_by_key<P1, P2, K, C, K1, K2, F>(&mut self, key1: K1, key2: K2, constructor: F)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              K: 'static + Hash + Eq, // Key
+              C: 'static + Any, // Child
+              K1: for<'r> Fn(&P1) -> K + 'static,
+              K2: for<'r> Fn(&P2) -> K + 'static,
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static
+    {
+        self.register_bridge_by_key(key1, key2, constructor)
+    }
+
+    /// See `Deps::bridge_filtered`.
+    pub fn bridge_filtered<P1, P2, C, P, F>(&mut self, filter: P, constructor: F)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              P: for<'r> Fn(&P1, &P2) -> bool + 'static,
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static
+    {
+        self.register_bridge_filtered(filter, constructor)
+    }
+
+    /// See `Deps::bridge_peers`.
+    pub fn bridge_peers<T, C, F>(&mut self, constructor: F)
+        where T: 'static + Any, // Peer
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut T, &mut T) -> Result<C> + 'static
+    {
+        self.register_bridge_peers(constructor)
+    }
+
+    /// See `Deps::bridge_with_teardown`.
+    pub fn bridge_with_teardown<P1, P2, C, F, T>(&mut self, constructor: F, teardown: T)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2) -> Result<C> + 'static,
+              T: for<'r> Fn(&mut P1, &mut P2, &mut C) + 'static
+    {
+        self.register_bridge_with_teardown(constructor, teardown)
+    }
+
+    /// See `Deps::bridge3`.
+    pub fn bridge3<P1, P2, P3, C, F>(&mut self, constructor: F)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              P3: 'static + Any, // Parent 3
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2, &mut P3) -> Result<C> + 'static
+    {
+        self.register_bridge3(constructor)
+    }
+
+    /// See `Deps::bridge4`.
+    pub fn bridge4<P1, P2, P3, P4, C, F>(&mut self, constructor: F)
+        where P1: 'static + Any, // Parent 1
+              P2: 'static + Any, // Parent 2
+              P3: 'static + Any, // Parent 3
+              P4: 'static + Any, // Parent 4
+              C: 'static + Any, // Child
+              F: for<'r> Fn(&LocalDeps, &mut P1, &mut P2, &mut P3, &mut P4) -> Result<C> + 'static
+    {
+        self.register_bridge4(constructor)
+    }
+
+    pub fn collectable<C, F>(&mut self, constructor: F)
+        where C: 'static + Any,
+              F: for<'r> Fn(&LocalDeps) -> C + 'static
+    {
+        self.collectable_ordered(Order::new(), constructor)
+            .expect("expected unordered registration to succeed")
+    }
+
+    /// See `Deps::collectable_ordered`.
+    pub fn collectable_ordered<C, F>(&mut self, order: Order, constructor: F) -> Result<()>
+        where C: 'static + Any,
+              F: for<'r> Fn(&LocalDeps) -> C + 'static
+    {
+        self.register_collectable(order, constructor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use {ExplodeReason, Result};
+    use constructed::Instance;
+    use super::*;
+
+    #[derive(Debug)]
+    struct Button(Rc<Cell<u32>>);
+    struct Label(Rc<RefCell<String>>);
+    struct Binding(Rc<Cell<u32>>, Rc<RefCell<String>>);
+
+    #[test]
+    fn bridges_values_that_are_not_send() {
+        let mut deps = LocalDeps::new();
+        let bound = Rc::new(Cell::new(0));
+        {
+            let bound = bound.clone();
+            deps.bridge(move |_: &LocalDeps, button: &mut Button, label: &mut Label| {
+                bound.set(bound.get() + 1);
+                Ok(Binding(button.0.clone(), label.0.clone()))
+            });
+        }
+
+        let clicks = Rc::new(Cell::new(0));
+        let mut button = deps.create(Button(clicks.clone())).unwrap();
+        let _label = deps.create(Label(Rc::new(RefCell::new("clicks".into())))).unwrap();
+
+        assert_eq!(1, bound.get());
+        button.write().unwrap().0.set(2);
+        let mut found = Vec::new();
+        button.with_children(|binding: &mut LocalScope<Binding>| {
+            let binding = binding.read().unwrap();
+            found.push(format!("{} {}", binding.0.get(), binding.1.borrow()));
+        });
+        assert_eq!(vec!["2 clicks".to_string()], found);
+    }
+
+    #[test]
+    fn passes_local_deps_to_callbacks() {
+        let mut deps = LocalDeps::new();
+        deps.collectable(|_: &LocalDeps| Rc::new("collected"));
+        deps.attach(|deps: &LocalDeps, _: &mut Button| -> Result<usize> {
+            Ok(deps.collect::<Rc<&str>>()?.iter().count())
+        });
+
+        let button = deps.create(Button(Rc::new(Cell::new(0)))).unwrap();
+
+        let collected = *button.child::<usize>().unwrap().read().unwrap();
+        assert_eq!(1, collected);
+    }
+
+    #[test]
+    fn gives_back_local_scope_if_value_is_still_shared() {
+        let mut deps = LocalDeps::new();
+        deps.bridge(|_: &LocalDeps, _: &mut Button, _: &mut Label| Ok(()));
+        let button = deps.create(Button(Rc::new(Cell::new(1)))).unwrap();
+        let leaked = match *button.get_instance() {
+            Instance::Shared(ref shared) => shared.clone(),
+            Instance::Isolated(_) => panic!("expected shared instance"),
+        };
+
+        let e = button.try_explode().unwrap_err();
+        assert_eq!(ExplodeReason::Shared, e.reason());
+
+        drop(leaked);
+        assert_eq!(1, e.into_scope().explode().0.get());
+    }
+
+    #[test]
+    fn bridges_local_values_with_same_key() {
+        let mut deps = LocalDeps::new();
+        deps.bridge_by_key(|button: &Button| button.0.get(),
+                           |label: &Label| label.0.borrow().len() as u32,
+                           |_: &LocalDeps, button: &mut Button, label: &mut Label| {
+                               Ok(Binding(button.0.clone(), label.0.clone()))
+                           });
+
+        let button = deps.create(Button(Rc::new(Cell::new(2)))).unwrap();
+        let _short = deps.create(Label(Rc::new(RefCell::new("ok".into())))).unwrap();
+        let _long = deps.create(Label(Rc::new(RefCell::new("cancel".into())))).unwrap();
+
+        let bindings = button.children::<Binding>();
+        assert_eq!(1, bindings.len());
+        assert_eq!("ok", *bindings[0].read().unwrap().1.borrow());
+    }
+
+    #[test]
+    fn explodes_value_after_its_bridged_children_are_destroyed() {
+        let mut deps = LocalDeps::new();
+        let dropped = Rc::new(Cell::new(0));
+        deps.bridge(|_: &LocalDeps, button: &mut Button, label: &mut Label| {
+            Ok(Binding(button.0.clone(), label.0.clone()))
+        });
+        {
+            let dropped = dropped.clone();
+            deps.when_dropped(move |_: &mut Binding| dropped.set(dropped.get() + 1));
+        }
+
+        let button = deps.create(Button(Rc::new(Cell::new(3)))).unwrap();
+        let label = deps.create(Label(Rc::new(RefCell::new("clicks".into())))).unwrap();
+        assert_eq!(0, dropped.get());
+
+        assert_eq!(3, button.explode().0.get());
+        assert_eq!(1, dropped.get());
+        assert_eq!("clicks", *label.explode().0.borrow());
+    }
+
+    #[test]
+    fn defers_bridges_with_locked_local_values_until_they_are_unlocked() {
+        let mut deps = LocalDeps::new();
+        deps.bridge(|_: &LocalDeps, button: &mut Button, label: &mut Label| {
+            Ok(Binding(button.0.clone(), label.0.clone()))
+        });
+
+        let mut button = deps.create(Button(Rc::new(Cell::new(0)))).unwrap();
+        let label = {
+            let guard = button.lock().unwrap();
+            let label = deps.create(Label(Rc::new(RefCell::new("clicks".into())))).unwrap();
+            assert!(label.child::<Binding>().is_none());
+            guard.unlock().unwrap();
+            label
+        };
+
+        assert!(label.child::<Binding>().is_some());
+        assert_eq!(1, button.children::<Binding>().len());
+    }
+}
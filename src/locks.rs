@@ -0,0 +1,409 @@
+//! Locks of shared values and their ordering.
+//!
+//! Shared values that are locked together are always locked in the same global order,
+//! by type and then by address. In debug builds, every lock on a shared value taken by
+//! bridges, actions or `Scope` is tracked, and two types locked in both orders are
+//! reported by `LockInversion::observed` as a potential deadlock.
+
+use std::any::{self, Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+thread_local!(
+    /// Addresses and type names of shared values locked on this thread, in locking order.
+    static LOCKED: RefCell<Vec<(usize, &'static str)>> = const { RefCell::new(Vec::new()) }
+);
+
+/// Pairs of type names that were locked in that order.
+static ORDER: Mutex<Vec<(&'static str, &'static str)>> = Mutex::new(Vec::new());
+
+/// Pairs of type names that were locked in both orders.
+static INVERSIONS: Mutex<Vec<LockInversion>> = Mutex::new(Vec::new());
+
+/// Two types whose shared values were locked in both orders, which may deadlock if
+/// it happens on different threads at the same time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LockInversion {
+    first: &'static str,
+    second: &'static str,
+}
+
+impl LockInversion {
+    /// All potential deadlocks observed so far. Always empty in release builds.
+    pub fn observed() -> Vec<LockInversion> {
+        INVERSIONS.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Type that was locked first, the first time.
+    pub fn first(&self) -> &'static str {
+        self.first
+    }
+
+    /// Type that was locked first, when the inversion was observed.
+    pub fn second(&self) -> &'static str {
+        self.second
+    }
+}
+
+/// Lock that guards a value shared with bridges.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SharedLock {
+    /// Every access locks the value exclusively.
+    #[default]
+    Mutex,
+    /// Any number of readers can access the value at once, bridges and writers
+    /// lock it exclusively.
+    RwLock,
+}
+
+/// Value shared with bridges, by `Deps` in a lock and by `LocalDeps` in `RefCell`.
+pub trait Share<T>: Clone {
+    /// Address of shared value, that identifies it while it is alive.
+    fn address(&self) -> usize;
+
+    fn strong_count(&self) -> usize;
+
+    fn is_poisoned(&self) -> bool;
+
+    /// Take the value out if this is the last reference to it, even if its lock is
+    /// poisoned, or give it back.
+    fn try_unwrap(self) -> Result<T, Self>;
+
+    /// Lock value exclusively, tracking it in debug builds.
+    fn lock(&self) -> LockResult<Locked<'_, T>>;
+
+    /// Lock value for reading, tracking it in debug builds.
+    fn read(&self) -> LockResult<ReadLocked<'_, T>>;
+}
+
+/// Value shared with bridges by `Deps`.
+#[derive(Debug)]
+pub enum Shared<T> {
+    Mutex(Arc<Mutex<T>>),
+    RwLock(Arc<RwLock<T>>),
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Shared<T> {
+        match *self {
+            Shared::Mutex(ref arc) => Shared::Mutex(arc.clone()),
+            Shared::RwLock(ref arc) => Shared::RwLock(arc.clone()),
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    /// Share value in specified lock.
+    pub fn new(value: T, lock: SharedLock) -> Shared<T> {
+        match lock {
+            SharedLock::Mutex => Shared::Mutex(Arc::new(Mutex::new(value))),
+            SharedLock::RwLock => Shared::RwLock(Arc::new(RwLock::new(value))),
+        }
+    }
+
+    /// Kind of lock that guards the value.
+    pub fn lock_kind(&self) -> SharedLock {
+        match *self {
+            Shared::Mutex(_) => SharedLock::Mutex,
+            Shared::RwLock(_) => SharedLock::RwLock,
+        }
+    }
+}
+
+impl<T> Share<T> for Shared<T> {
+    fn address(&self) -> usize {
+        match *self {
+            Shared::Mutex(ref arc) => Arc::as_ptr(arc) as *const () as usize,
+            Shared::RwLock(ref arc) => Arc::as_ptr(arc) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match *self {
+            Shared::Mutex(ref arc) => Arc::strong_count(arc),
+            Shared::RwLock(ref arc) => Arc::strong_count(arc),
+        }
+    }
+
+    fn is_poisoned(&self) -> bool {
+        match *self {
+            Shared::Mutex(ref arc) => arc.is_poisoned(),
+            Shared::RwLock(ref arc) => arc.is_poisoned(),
+        }
+    }
+
+    fn try_unwrap(self) -> Result<T, Shared<T>> {
+        match self {
+            Shared::Mutex(arc) => {
+                Arc::try_unwrap(arc)
+                    .map(|mutex| mutex.into_inner().unwrap_or_else(PoisonError::into_inner))
+                    .map_err(Shared::Mutex)
+            }
+            Shared::RwLock(arc) => {
+                Arc::try_unwrap(arc)
+                    .map(|rw| rw.into_inner().unwrap_or_else(PoisonError::into_inner))
+                    .map_err(Shared::RwLock)
+            }
+        }
+    }
+
+    fn lock(&self) -> LockResult<Locked<'_, T>> {
+        let tracked = Tracked::new(self.address(), any::type_name::<T>());
+        match *self {
+            Shared::Mutex(ref mutex) => {
+                map_lock(mutex.lock(), |guard| {
+                    Locked {
+                        guard: WriteGuard::Mutex(guard),
+                        _tracked: tracked,
+                    }
+                })
+            }
+            Shared::RwLock(ref rw) => {
+                map_lock(rw.write(), |guard| {
+                    Locked {
+                        guard: WriteGuard::RwLock(guard),
+                        _tracked: tracked,
+                    }
+                })
+            }
+        }
+    }
+
+    /// Value behind a mutex is locked exclusively.
+    fn read(&self) -> LockResult<ReadLocked<'_, T>> {
+        let tracked = Tracked::new(self.address(), any::type_name::<T>());
+        match *self {
+            Shared::Mutex(ref mutex) => {
+                map_lock(mutex.lock(), |guard| {
+                    ReadLocked {
+                        guard: ReadGuard::Mutex(guard),
+                        _tracked: tracked,
+                    }
+                })
+            }
+            Shared::RwLock(ref rw) => {
+                map_lock(rw.read(), |guard| {
+                    ReadLocked {
+                        guard: ReadGuard::RwLock(guard),
+                        _tracked: tracked,
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Value shared with bridges by `LocalDeps`, on a single thread.
+#[derive(Debug)]
+pub struct LocalShared<T>(Rc<RefCell<T>>);
+
+impl<T> Clone for LocalShared<T> {
+    fn clone(&self) -> LocalShared<T> {
+        LocalShared(self.0.clone())
+    }
+}
+
+impl<T> LocalShared<T> {
+    pub fn new(value: T) -> LocalShared<T> {
+        LocalShared(Rc::new(RefCell::new(value)))
+    }
+}
+
+impl<T> Share<T> for LocalShared<T> {
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+
+    fn strong_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
+    fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    fn try_unwrap(self) -> Result<T, LocalShared<T>> {
+        Rc::try_unwrap(self.0).map(RefCell::into_inner).map_err(LocalShared)
+    }
+
+    /// Panics if the value is already borrowed.
+    fn lock(&self) -> LockResult<Locked<'_, T>> {
+        Ok(Locked {
+            guard: WriteGuard::Local(self.0.borrow_mut()),
+            _tracked: Tracked::new(self.address(), any::type_name::<T>()),
+        })
+    }
+
+    /// Panics if the value is already borrowed mutably.
+    fn read(&self) -> LockResult<ReadLocked<'_, T>> {
+        Ok(ReadLocked {
+            guard: ReadGuard::Local(self.0.borrow()),
+            _tracked: Tracked::new(self.address(), any::type_name::<T>()),
+        })
+    }
+}
+
+/// Map the guard of a lock result, keeping the poison.
+pub fn map_lock<G, L, F>(result: LockResult<G>, f: F) -> LockResult<L>
+    where F: FnOnce(G) -> L
+{
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(e) => Err(PoisonError::new(f(e.into_inner()))),
+    }
+}
+
+/// Position of shared value in global lock order.
+pub fn rank<T: Any, S: Share<T>>(shared: &S) -> (TypeId, usize) {
+    (TypeId::of::<T>(), shared.address())
+}
+
+enum WriteGuard<'a, T: 'a> {
+    Mutex(MutexGuard<'a, T>),
+    RwLock(RwLockWriteGuard<'a, T>),
+    Local(RefMut<'a, T>),
+}
+
+enum ReadGuard<'a, T: 'a> {
+    Mutex(MutexGuard<'a, T>),
+    RwLock(RwLockReadGuard<'a, T>),
+    Local(Ref<'a, T>),
+}
+
+/// Exclusive lock of shared value, tracked while it is held.
+pub struct Locked<'a, T: 'a> {
+    guard: WriteGuard<'a, T>,
+    _tracked: Tracked,
+}
+
+impl<'a, T> Deref for Locked<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.guard {
+            WriteGuard::Mutex(ref guard) => guard,
+            WriteGuard::RwLock(ref guard) => guard,
+            WriteGuard::Local(ref guard) => guard,
+        }
+    }
+}
+
+impl<'a, T> DerefMut for Locked<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self.guard {
+            WriteGuard::Mutex(ref mut guard) => guard,
+            WriteGuard::RwLock(ref mut guard) => guard,
+            WriteGuard::Local(ref mut guard) => guard,
+        }
+    }
+}
+
+/// Read lock of shared value, tracked while it is held.
+pub struct ReadLocked<'a, T: 'a> {
+    guard: ReadGuard<'a, T>,
+    _tracked: Tracked,
+}
+
+impl<'a, T> Deref for ReadLocked<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.guard {
+            ReadGuard::Mutex(ref guard) => guard,
+            ReadGuard::RwLock(ref guard) => guard,
+            ReadGuard::Local(ref guard) => guard,
+        }
+    }
+}
+
+/// Marks shared value as locked on this thread for as long as it lives.
+struct Tracked {
+    address: usize,
+}
+
+impl Tracked {
+    fn new(address: usize, name: &'static str) -> Tracked {
+        if cfg!(debug_assertions) {
+            LOCKED.with(|locked| {
+                let mut locked = locked.borrow_mut();
+                for &(_, held) in locked.iter() {
+                    if held != name {
+                        observe(held, name);
+                    }
+                }
+                locked.push((address, name));
+            });
+        }
+        Tracked { address }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if cfg!(debug_assertions) {
+            LOCKED.with(|locked| {
+                let mut locked = locked.borrow_mut();
+                if let Some(position) = locked.iter().rposition(|&(address, _)| address == self.address) {
+                    locked.remove(position);
+                }
+            });
+        }
+    }
+}
+
+/// Record that `second` was locked while `first` was held, and keep the inversion for
+/// `LockInversion::observed` if the opposite order was observed before.
+fn observe(first: &'static str, second: &'static str) {
+    let mut order = ORDER.lock().unwrap_or_else(PoisonError::into_inner);
+    if order.contains(&(first, second)) {
+        return;
+    }
+    if order.contains(&(second, first)) {
+        let inversion = LockInversion {
+            first: second,
+            second: first,
+        };
+        INVERSIONS.lock().unwrap_or_else(PoisonError::into_inner).push(inversion);
+    }
+    order.push((first, second));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct First;
+    struct Second;
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn should_report_types_locked_in_both_orders() {
+        let first = Shared::new(First, SharedLock::Mutex);
+        let second = Shared::new(Second, SharedLock::RwLock);
+        {
+            let _first = first.lock().unwrap();
+            let _second = second.read().unwrap();
+        }
+        assert!(LockInversion::observed().iter().all(|i| i.first() != any::type_name::<First>()));
+
+        let _second = second.lock().unwrap();
+        let _first = first.read().unwrap();
+        assert!(LockInversion::observed().contains(&LockInversion {
+            first: any::type_name::<First>(),
+            second: any::type_name::<Second>(),
+        }));
+    }
+
+    #[test]
+    fn should_order_by_type_then_address() {
+        let a = Shared::new(1u8, SharedLock::Mutex);
+        let b = Shared::new(2u8, SharedLock::RwLock);
+        let c = Shared::new(3u16, SharedLock::Mutex);
+
+        assert_eq!(rank(&a) < rank(&b), a.address() < b.address());
+        assert_eq!(rank(&a) < rank(&c), rank(&b) < rank(&c));
+    }
+}
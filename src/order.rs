@@ -0,0 +1,192 @@
+//! Ordering of registrations invoked for the same type.
+
+/// Position of a registration among other registrations of the same kind for the same type,
+/// passed to `Deps::attach_ordered`, `Deps::bridge_ordered`, `Deps::collectable_ordered`
+/// or `Deps::when_ready_ordered`.
+///
+/// Registrations run before the ones they are ordered `before`, and after the ones they
+/// are ordered `after`, found by their `label`. Registrations not constrained that way
+/// run in order of decreasing `priority`, and ones of equal priority in order of registration.
+///
+/// Attached and bridged constructors of a type are ordered together, so a bridge can run
+/// before or after specific attached constructors of each of its parents.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Order {
+    label: Option<&'static str>,
+    priority: i32,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+impl Order {
+    /// Order of a registration without constraints and with priority 0, the same as
+    /// registrations that are not ordered.
+    pub fn new() -> Order {
+        Order::default()
+    }
+
+    /// Name that other registrations can be ordered `before` or `after`.
+    pub fn label(mut self, label: &'static str) -> Order {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn priority(mut self, priority: i32) -> Order {
+        self.priority = priority;
+        self
+    }
+
+    /// Run before the registration with `label`, if there is one.
+    pub fn before(mut self, label: &'static str) -> Order {
+        self.before.push(label);
+        self
+    }
+
+    /// Run after the registration with `label`, if there is one.
+    pub fn after(mut self, label: &'static str) -> Order {
+        self.after.push(label);
+        self
+    }
+}
+
+/// Registrations for one type, kept in resolved order.
+#[derive(Clone)]
+pub struct Ordered<T> {
+    /// Registrations in order of registration.
+    entries: Vec<(Order, T)>,
+    /// Indices of `entries` in the order they should run.
+    resolved: Vec<usize>,
+}
+
+impl<T> Default for Ordered<T> {
+    fn default() -> Self {
+        Ordered {
+            entries: Vec::new(),
+            resolved: Vec::new(),
+        }
+    }
+}
+
+impl<T> Ordered<T> {
+    /// Add registration, or return a description of the conflict if its order contradicts
+    /// the order of registrations already added, leaving them unchanged.
+    pub fn push(&mut self, order: Order, item: T) -> Result<(), String> {
+        if let Some(label) = order.label {
+            if self.entries.iter().any(|(other, _)| other.label == Some(label)) {
+                return Err(format!("label {:?} is already used", label));
+            }
+        }
+        self.entries.push((order, item));
+        match resolve(&self.entries) {
+            Ok(resolved) => {
+                self.resolved = resolved;
+                Ok(())
+            }
+            Err(labels) => {
+                self.entries.pop();
+                Err(format!("registrations {} have contradictory order", labels.join(", ")))
+            }
+        }
+    }
+
+    /// Registrations in the order they should run.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.resolved.iter().map(move |&i| &self.entries[i].1)
+    }
+}
+
+/// Order entries so that every one of them comes after all the entries it must follow,
+/// picking the one with highest priority, registered first, among the ones that can come next.
+///
+/// Returns labels of entries that could not be ordered, if their constraints contradict.
+fn resolve<T>(entries: &[(Order, T)]) -> Result<Vec<usize>, Vec<&'static str>> {
+    let index_of = |label: &'static str| {
+        entries.iter().position(|(order, _)| order.label == Some(label))
+    };
+    // `follows[i]` lists the entries that must come before entry `i`.
+    let mut follows: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    for (i, (order, _)) in entries.iter().enumerate() {
+        follows[i].extend(order.after.iter().cloned().filter_map(index_of));
+        for j in order.before.iter().cloned().filter_map(index_of) {
+            follows[j].push(i);
+        }
+    }
+
+    let mut resolved = Vec::with_capacity(entries.len());
+    let mut placed = vec![false; entries.len()];
+    while resolved.len() < entries.len() {
+        let next = (0..entries.len())
+            .filter(|&i| !placed[i] && follows[i].iter().all(|&j| placed[j]))
+            .min_by_key(|&i| (-i64::from(entries[i].0.priority), i));
+        match next {
+            Some(i) => {
+                placed[i] = true;
+                resolved.push(i);
+            }
+            None => {
+                return Err((0..entries.len())
+                    .filter(|&i| !placed[i])
+                    .filter_map(|i| entries[i].0.label)
+                    .collect())
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ordered(orders: Vec<Order>) -> Vec<usize> {
+        let mut ordered = Ordered::default();
+        for (i, order) in orders.into_iter().enumerate() {
+            ordered.push(order, i).unwrap();
+        }
+        ordered.iter().cloned().collect()
+    }
+
+    #[test]
+    fn should_keep_registration_order_of_equal_priorities() {
+        assert_eq!(vec![0, 1, 2], ordered(vec![Order::new(), Order::new(), Order::new()]));
+    }
+
+    #[test]
+    fn should_order_by_decreasing_priority() {
+        let orders = vec![Order::new().priority(-1), Order::new(), Order::new().priority(5), Order::new()];
+
+        assert_eq!(vec![2, 1, 3, 0], ordered(orders));
+    }
+
+    #[test]
+    fn should_order_before_and_after_labels_regardless_of_priority() {
+        let orders = vec![
+            Order::new().label("pool").priority(10),
+            Order::new().label("log").before("pool"),
+            Order::new().after("log").before("pool"),
+            Order::new().after("missing"),
+        ];
+
+        assert_eq!(vec![1, 2, 0, 3], ordered(orders));
+    }
+
+    #[test]
+    fn should_reject_contradictory_order() {
+        let mut ordered = Ordered::default();
+        ordered.push(Order::new().label("a").before("b"), 0).unwrap();
+        ordered.push(Order::new().label("b").before("c"), 1).unwrap();
+
+        let e = ordered.push(Order::new().label("c").before("a"), 2).unwrap_err();
+
+        assert_eq!("registrations a, b, c have contradictory order", e);
+        assert_eq!(vec![0, 1], ordered.iter().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_reject_repeated_label() {
+        let mut ordered = Ordered::default();
+        ordered.push(Order::new().label("a"), 0).unwrap();
+
+        assert_eq!("label \"a\" is already used", ordered.push(Order::new().label("a"), 1).unwrap_err());
+    }
+}
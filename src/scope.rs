@@ -1,48 +1,485 @@
-use std::any::Any;
-use std::sync::Arc;
-use std::sync::LockResult;
+use std::any::{self, Any};
+use std::fmt;
+use std::sync::{Arc, LockResult, PoisonError};
+use std::marker::PhantomData;
 use std::mem;
-use constructed::{Instance, AnyInstance, MaybeMutexGuard};
+use std::ops::{Deref, DerefMut};
+use constructed::{Instance, AnyInstance, MaybeMutexGuard, MaybeReadGuard};
+use error::{ExplodeError, ExplodeReason};
+use flavor::{Flavor, Threaded};
+use inceptor::{self, Destructor, SharedChild};
+use locks::{self, Share};
+use inspect::{Child, ScopeInfo, Sharing, TreePrinter, Visitor};
 
-#[derive(Debug)]
-pub struct Scope<T> {
-    obj: Instance<T>,
-    childs: Vec<Box<Any>>,
+/// Value together with all the dependencies created for it.
+///
+/// Scope can be sent to another thread and shared between threads if its value is
+/// `Send + Sync`, since a shared value may be read from many threads at once behind
+/// `SharedLock::RwLock`. Dependencies created by `Deps` are always `Send + Sync`, values
+/// that are not can only be created by `LocalDeps`.
+///
+/// ```compile_fail
+/// use std::rc::Rc;
+/// use std::thread;
+///
+/// let scope = di::Deps::new().create(Rc::new(1)).unwrap();
+/// thread::spawn(move || drop(scope));
+/// ```
+///
+/// ```compile_fail
+/// use std::rc::Rc;
+///
+/// let mut deps = di::Deps::new();
+/// deps.attach(|_: &di::Deps, value: &mut u32| Ok(Rc::new(*value)));
+/// ```
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use std::thread;
+///
+/// let mut deps = di::Deps::new();
+/// deps.set_shared_lock(di::SharedLock::RwLock);
+/// let scope = deps.create(Cell::new(1)).unwrap();
+/// thread::spawn(move || drop(scope));
+/// ```
+///
+/// When scope is dropped or exploded, its children are destroyed in reverse order of
+/// their construction, each child scope destroying its own children first. Bridged
+/// children are destroyed as soon as any one of their parents is, before that parent value.
+///
+/// Hooks registered with `Deps::when_dropping` run before children are destroyed, and
+/// hooks registered with `Deps::when_dropped` run after.
+pub struct Scope<T, F: Flavor = Threaded> {
+    /// Value of the scope, `None` only after it was taken out by `try_explode`.
+    obj: Option<Instance<T, F>>,
+    childs: Vec<Box<F::Child>>,
+    hooks: DropHooks<T, F>,
 }
 
-impl<T: Any> Scope<T> {
-    pub fn from_any_instance(obj: AnyInstance, childs: Vec<Box<Any>>) -> Scope<T> {
+impl<T, F: Flavor> fmt::Debug for Scope<T, F>
+    where Instance<T, F>: fmt::Debug,
+          F::Child: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("obj", &self.obj)
+            .field("childs", &self.childs)
+            .field("hooks", &self.hooks)
+            .finish()
+    }
+}
+
+/// Callback registered for a type with `Deps::when_dropping` or `Deps::when_dropped`.
+pub(crate) type AnyDropHook<F> = Arc<<F as Flavor>::DropHook>;
+
+/// Hooks to invoke before and after children of a scope are destroyed.
+pub(crate) struct DropHooks<T, F: Flavor> {
+    dropping: Vec<AnyDropHook<F>>,
+    dropped: Vec<AnyDropHook<F>>,
+    /// Invokes a hook with the value of the scope.
+    call: fn(&F::DropHook, &mut T),
+}
+
+impl<T: Any, F: Flavor> DropHooks<T, F> {
+    pub(crate) fn new(dropping: &[AnyDropHook<F>], dropped: &[AnyDropHook<F>]) -> DropHooks<T, F> {
+        fn call<T: Any, F: Flavor>(hook: &F::DropHook, value: &mut T) {
+            hook(value)
+        }
+
+        DropHooks {
+            dropping: dropping.to_vec(),
+            dropped: dropped.to_vec(),
+            call: call::<T, F>,
+        }
+    }
+}
+
+impl<T, F: Flavor> DropHooks<T, F> {
+    fn none() -> DropHooks<T, F> {
+        fn skip<T, F: Flavor>(_: &F::DropHook, _: &mut T) {}
+
+        DropHooks {
+            dropping: Vec::new(),
+            dropped: Vec::new(),
+            call: skip::<T, F>,
+        }
+    }
+}
+
+impl<T, F: Flavor> fmt::Debug for DropHooks<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DropHooks")
+            .field("dropping", &self.dropping.len())
+            .field("dropped", &self.dropped.len())
+            .finish()
+    }
+}
+
+/// Invoke `hooks` with the value, locking it if it is shared.
+///
+/// Hooks run while the scope is destroyed, so a poisoned lock is ignored.
+fn run_hooks<T, F: Flavor>(obj: &mut Option<Instance<T, F>>,
+                           hooks: &[AnyDropHook<F>],
+                           call: fn(&F::DropHook, &mut T)) {
+    if let (Some(obj), false) = (obj.as_mut(), hooks.is_empty()) {
+        let mut value = obj.lock().unwrap_or_else(PoisonError::into_inner);
+        for hook in hooks {
+            call(hook, &mut value);
+        }
+    }
+}
+
+impl<T, F: Flavor> Scope<T, F> {
+    /// Destroy children of this scope, with hooks registered for its value around that.
+    ///
+    /// Hooks run only once, even if children are destroyed again.
+    fn destroy_children(&mut self) {
+        let hooks = mem::replace(&mut self.hooks, DropHooks::none());
+        run_hooks(&mut self.obj, &hooks.dropping, hooks.call);
+        drop_in_reverse(mem::take(&mut self.childs));
+        run_hooks(&mut self.obj, &hooks.dropped, hooks.call);
+    }
+
+    fn instance(&self) -> &Instance<T, F> {
+        self.obj.as_ref().expect("expected value to remain in scope until it is exploded")
+    }
+
+    fn instance_mut(&mut self) -> &mut Instance<T, F> {
+        self.obj.as_mut().expect("expected value to remain in scope until it is exploded")
+    }
+}
+
+/// Drop children in reverse order of their construction.
+pub(crate) fn drop_in_reverse<C>(mut children: Vec<C>) {
+    while let Some(child) = children.pop() {
+        mem::drop(child);
+    }
+}
+
+impl<T, F: Flavor> Drop for Scope<T, F> {
+    fn drop(&mut self) {
+        self.destroy_children();
+    }
+}
+
+impl<T: Any, F: Flavor> Scope<T, F> {
+    pub fn from_any_instance(obj: AnyInstance, childs: Vec<Box<F::Child>>) -> Scope<T, F> {
+        Scope::with_hooks(obj, childs, DropHooks::none())
+    }
+
+    pub(crate) fn with_hooks(obj: AnyInstance, childs: Vec<Box<F::Child>>, hooks: DropHooks<T, F>) -> Scope<T, F> {
+        Scope {
+            obj: Some(obj.downcast()),
+            childs,
+            hooks,
+        }
+    }
+
+    /// Scope left with value whose children were already destroyed.
+    fn without_children(obj: Instance<T, F>) -> Scope<T, F> {
         Scope {
-            obj: obj.downcast(),
-            childs: childs,
+            obj: Some(obj),
+            childs: Vec::new(),
+            hooks: DropHooks::none(),
         }
     }
 
     pub fn explode(self) -> T {
-        mem::drop(self.childs); // Childs contain a special "destructor" that
-                                // will free up the arc when dropped.
-                                // To make behaviour consistent, we are dropping childs before
-                                // parent in all cases.
-        match self.obj {
-            Instance::Isolated(obj) => obj,
-            Instance::Shared(arc) => {
-                Arc::try_unwrap(arc)
-                    .ok()
-                    .expect("expected arc to be last remaining")
-                    .into_inner()
-                    .expect("expected to lock value before exploding")
+        match self.try_explode() {
+            Ok(value) => value,
+            Err(e) => {
+                match e.reason() {
+                    ExplodeReason::Shared => panic!("expected arc to be last remaining"),
+                    ExplodeReason::Poisoned => panic!("expected to lock value before exploding"),
+                }
+            }
+        }
+    }
+
+    /// Take the value out of this scope, destroying all its children, or give the scope
+    /// back if the value is still shared or its mutex is poisoned.
+    ///
+    /// Value that another thread is bridging at the moment is still shared, and the scope
+    /// is given back without waiting for that bridge.
+    ///
+    /// The scope is given back intact, unless the value became shared by another thread
+    /// after children were destroyed; then it is given back without children.
+    pub fn try_explode(mut self) -> Result<T, ExplodeError<T, Scope<T, F>>> {
+        if let Some(Instance::Shared(ref shared)) = self.obj {
+            // Every bridge destructor holds one more reference until it is dropped.
+            let bridges: Vec<&Destructor<F>> = self.childs
+                .iter()
+                .filter_map(|c| c.as_any().downcast_ref::<Destructor<F>>())
+                .collect();
+            if shared.is_poisoned() {
+                return Err(ExplodeError::new(self, ExplodeReason::Poisoned));
+            }
+            if shared.strong_count() > bridges.len() + 1 || bridges.iter().any(|d| d.is_bridging()) {
+                return Err(ExplodeError::new(self, ExplodeReason::Shared));
+            }
+        }
+
+        self.destroy_children(); // Childs contain a special "destructor" that
+                                 // will free up the arc when dropped.
+                                 // To make behaviour consistent, we are dropping
+                                 // childs before parent in all cases.
+        match self.obj.take().expect("expected value to remain in scope until it is exploded") {
+            Instance::Isolated(obj) => Ok(obj),
+            Instance::Shared(shared) => {
+                shared.try_unwrap().map_err(|shared| {
+                    let obj = Instance::Shared(shared);
+                    ExplodeError::new(Scope::without_children(obj), ExplodeReason::Shared)
+                })
             }
         }
     }
 
+    /// Lock the value for writing.
+    ///
+    /// Bridges of this value with values created on this thread while it is locked are
+    /// deferred instead of deadlocking on it. They are created once this thread unlocks
+    /// all values it locked, with registrations `Deps` had when the values were created.
+    ///
+    /// A deferred bridge that fails leaves its parents without that child, as they were
+    /// already created. `MaybeMutexGuard::unlock` returns the errors of such bridges,
+    /// while dropping the guard ignores them.
     pub fn lock<'a>(&'a mut self) -> LockResult<MaybeMutexGuard<'a, T>> {
-        self.obj.lock()
+        self.instance_mut().lock()
+    }
+
+    /// Lock the value for reading.
+    ///
+    /// Value shared with `SharedLock::RwLock` can be read by many threads at once,
+    /// value shared with `SharedLock::Mutex` is locked exclusively. Bridges are deferred
+    /// the same as with `lock`.
+    pub fn read<'a>(&'a self) -> LockResult<MaybeReadGuard<'a, T>> {
+        self.instance().read()
+    }
+
+    /// Lock the value for writing, same as `lock`.
+    pub fn write<'a>(&'a mut self) -> LockResult<MaybeMutexGuard<'a, T>> {
+        self.instance_mut().lock()
+    }
+
+    pub fn get_instance(&self) -> &Instance<T, F> {
+        self.instance()
+    }
+
+    /// First child scope of type `C`, searched depth-first among attached children and
+    /// their children, including scopes created by bridges they take part in.
+    ///
+    /// Bridged scopes stay locked while they are borrowed, and lookups on the same thread
+    /// skip bridged scopes it already keeps locked. Search does not descend into scopes it
+    /// finds, nor into bridged scopes.
+    pub fn child<C: Any>(&self) -> Option<ChildRef<'_, Scope<C, F>>> {
+        self.children().into_iter().next()
+    }
+
+    /// All child scopes of type `C`, in the same order as `child` searches them.
+    pub fn children<C: Any>(&self) -> Vec<ChildRef<'_, Scope<C, F>>> {
+        let mut found = Vec::new();
+        find(&self.childs, &mut found);
+        found
+    }
+
+    pub fn child_mut<C: Any>(&mut self) -> Option<ChildMut<'_, Scope<C, F>>> {
+        self.children_mut().into_iter().next()
+    }
+
+    pub fn children_mut<C: Any>(&mut self) -> Vec<ChildMut<'_, Scope<C, F>>> {
+        let mut found = Vec::new();
+        find_mut(&mut self.childs, &mut found);
+        found
     }
 
-    pub fn get_instance(&self) -> &Instance<T> {
-        &self.obj
+    /// Lock the value of first child scope of type `C`.
+    ///
+    /// Bridged scope stays locked for as long as its value.
+    pub fn lock_child<'a, C: Any>(&'a mut self) -> Option<LockResult<MaybeMutexGuard<'a, C>>> {
+        self.child_mut::<C>().map(ChildMut::into_lock)
+    }
+
+    /// Call `f` for every child scope of type `C`, including scopes created by bridges
+    /// this scope value takes part in.
+    ///
+    /// Bridged scopes are locked while they are passed to `f`, but their bridge is not,
+    /// so `f` may create or drop values of bridge parent types. A bridged scope whose
+    /// parent `f` drops is torn down and dropped only after `f` returns.
+    pub fn with_children<C: Any, G>(&mut self, mut f: G)
+        where G: FnMut(&mut Scope<C, F>)
+    {
+        for child in &mut self.childs {
+            with_children(&mut **child, &mut f);
+        }
+    }
+
+    /// Children attached to this scope, including bridge destructors.
+    pub(crate) fn owned_children(&self) -> &[Box<F::Child>] {
+        &self.childs
+    }
+
+    pub(crate) fn owned_children_mut(&mut self) -> &mut [Box<F::Child>] {
+        &mut self.childs
+    }
+
+    /// Walk this scope and all scopes and bridges it keeps alive.
+    pub fn inspect(&self, visitor: &mut dyn Visitor) {
+        let sharing = match *self.instance() {
+            Instance::Isolated(_) => Sharing::Isolated,
+            Instance::Shared(_) => Sharing::Shared,
+        };
+        let info = ScopeInfo::new(any::type_name::<T>(), sharing);
+        visitor.enter_scope(&info);
+        for child in &self.childs {
+            child.accept(visitor);
+        }
+        visitor.leave_scope(&info);
+    }
+
+    /// Print this scope and all scopes and bridges it keeps alive, one per line.
+    pub fn tree(&self) -> String {
+        let mut printer = TreePrinter::new();
+        self.inspect(&mut printer);
+        printer.into_string()
     }
 }
 
-unsafe impl<T> Send for Scope<T> {}
-unsafe impl<T> Sync for Scope<T> {}
+impl<T: Any, F: Flavor> Child<F> for Scope<T, F> {
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        self.inspect(visitor)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn children(&self) -> &[Box<F::Child>] {
+        self.owned_children()
+    }
+
+    fn children_mut(&mut self) -> &mut [Box<F::Child>] {
+        self.owned_children_mut()
+    }
+}
+
+/// Child scope found by `Scope::child` or `Scope::children`.
+///
+/// If the scope was created by a bridge, it stays locked until this is dropped.
+pub struct ChildRef<'a, S: 'a> {
+    scope: *const S,
+    /// `ChildLock` of bridged scope, which keeps `scope` alive.
+    _bridged: Option<Box<dyn Any>>,
+    lifetime: PhantomData<&'a S>,
+}
+
+impl<'a, S> ChildRef<'a, S> {
+    fn owned(scope: &'a S) -> Self {
+        ChildRef { scope, _bridged: None, lifetime: PhantomData }
+    }
+}
+
+impl<'a, C: Any, F: Flavor> ChildRef<'a, Scope<C, F>> {
+    fn bridged(child: SharedChild<F>) -> Option<Self> {
+        let lock = inceptor::lock_child(child)?;
+        let scope: *const Scope<C, F> = lock.as_any().downcast_ref::<Scope<C, F>>()?;
+        Some(ChildRef { scope, _bridged: Some(Box::new(lock)), lifetime: PhantomData })
+    }
+}
+
+impl<'a, S> Deref for ChildRef<'a, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        // Safety: `scope` is valid for as long as `_bridged` or the borrow of `'a`.
+        unsafe { &*self.scope }
+    }
+}
+
+/// Child scope found by `Scope::child_mut` or `Scope::children_mut`.
+///
+/// If the scope was created by a bridge, it stays locked until this is dropped.
+pub struct ChildMut<'a, S: 'a> {
+    scope: *mut S,
+    /// `ChildLock` of bridged scope, which keeps `scope` alive.
+    bridged: Option<Box<dyn Any>>,
+    lifetime: PhantomData<&'a mut S>,
+}
+
+impl<'a, S> ChildMut<'a, S> {
+    fn owned(scope: &'a mut S) -> Self {
+        ChildMut { scope, bridged: None, lifetime: PhantomData }
+    }
+}
+
+impl<'a, C: Any, F: Flavor> ChildMut<'a, Scope<C, F>> {
+    fn bridged(child: SharedChild<F>) -> Option<Self> {
+        let mut lock = inceptor::lock_child(child)?;
+        let scope: *mut Scope<C, F> = lock.as_any_mut().downcast_mut::<Scope<C, F>>()?;
+        Some(ChildMut { scope, bridged: Some(Box::new(lock)), lifetime: PhantomData })
+    }
+
+    /// Lock the value of this scope, keeping bridged scope locked for as long as it.
+    pub(crate) fn into_lock(self) -> LockResult<MaybeMutexGuard<'a, C>> {
+        // Safety: see `deref_mut`, the guard takes over `self.bridged`.
+        let scope = unsafe { &mut *self.scope };
+        let bridged = self.bridged;
+        locks::map_lock(scope.lock(), |guard| guard.keeping(bridged))
+    }
+}
+
+impl<'a, S> Deref for ChildMut<'a, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        // Safety: see `deref_mut`.
+        unsafe { &*self.scope }
+    }
+}
+
+impl<'a, S> DerefMut for ChildMut<'a, S> {
+    fn deref_mut(&mut self) -> &mut S {
+        // Safety: `scope` is valid and not otherwise borrowed for as long as `bridged`
+        // or the borrow of `'a`.
+        unsafe { &mut *self.scope }
+    }
+}
+
+fn find<'a, C: Any, F: Flavor>(childs: &'a [Box<F::Child>], found: &mut Vec<ChildRef<'a, Scope<C, F>>>) {
+    for child in childs {
+        match child.as_any().downcast_ref::<Scope<C, F>>() {
+            Some(scope) => found.push(ChildRef::owned(scope)),
+            None => {
+                find(child.children(), found);
+                found.extend(child.bridged().into_iter().filter_map(ChildRef::bridged));
+            }
+        }
+    }
+}
+
+fn find_mut<'a, C: Any, F: Flavor>(childs: &'a mut [Box<F::Child>], found: &mut Vec<ChildMut<'a, Scope<C, F>>>) {
+    for child in childs {
+        if child.as_any().is::<Scope<C, F>>() {
+            found.extend(child.as_any_mut().downcast_mut::<Scope<C, F>>().map(ChildMut::owned));
+        } else {
+            let bridged = child.bridged();
+            find_mut(child.children_mut(), found);
+            found.extend(bridged.into_iter().filter_map(ChildMut::bridged));
+        }
+    }
+}
+
+fn with_children<C: Any, F: Flavor>(child: &mut F::Child, f: &mut dyn FnMut(&mut Scope<C, F>)) {
+    if let Some(scope) = child.as_any_mut().downcast_mut::<Scope<C, F>>() {
+        return f(scope);
+    }
+    for grandchild in child.children_mut() {
+        with_children(&mut **grandchild, f);
+    }
+    for mut bridged in child.bridged().into_iter().filter_map(inceptor::lock_child) {
+        with_children(&mut *bridged, f);
+    }
+}